@@ -0,0 +1,24 @@
+//! 字句解析器のベンチマーク。
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use parser::lexer::Lexer;
+
+/// 数値の多い大きな入力を作る。
+fn numeric_heavy_input() -> String {
+    (0..20_000)
+        .map(|i| format!("x{} = {} * 1234567 + {};\n", i % 100, i, i * 7))
+        .collect()
+}
+
+fn tokenize(c: &mut Criterion) {
+    let input = numeric_heavy_input();
+
+    c.bench_function("tokenize numeric heavy", |b| {
+        b.iter(|| Lexer::new(black_box(&input)).lex().unwrap())
+    });
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
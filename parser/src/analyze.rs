@@ -0,0 +1,345 @@
+use std::fmt::Write;
+
+use crate::{
+    ast::{Expression, For, If, Program, Statement, While},
+    eval::is_truthy,
+    fold::const_value,
+};
+
+/// 制御フローグラフ。[`build_cfg`]で作る。
+///
+/// ブロックの番号は`blocks`の添字で、[`Cfg::ENTRY`]が入口、[`Cfg::EXIT`]が出口になる。
+#[derive(Debug, PartialEq)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+/// 途中で分岐も合流もしない文の並び。
+#[derive(Debug, Default, PartialEq)]
+pub struct BasicBlock {
+    /// 実行する文と、ブロックの末尾で分岐する場合はその条件を、[`Statement`]の`Display`形式で並べる
+    pub stmts: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EdgeKind {
+    /// 無条件に移る
+    Always,
+    /// 条件が真の場合に移る
+    True,
+    /// 条件が偽の場合に移る
+    False,
+}
+
+impl Cfg {
+    pub const ENTRY: usize = 0;
+    pub const EXIT: usize = 1;
+
+    /// Graphvizの`dot`形式で表示する。
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let name = match i {
+                Self::ENTRY => "entry".to_string(),
+                Self::EXIT => "exit".to_string(),
+                i => format!("B{}", i),
+            };
+            let mut label = name;
+            for stmt in &block.stmts {
+                label.push_str("\\n");
+                label.push_str(&stmt.replace('\\', "\\\\").replace('"', "\\\""));
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", i, label).unwrap();
+        }
+        for edge in &self.edges {
+            let attr = match edge.kind {
+                EdgeKind::Always => "",
+                EdgeKind::True => " [label=\"true\"]",
+                EdgeKind::False => " [label=\"false\"]",
+            };
+            writeln!(dot, "    b{} -> b{}{};", edge.from, edge.to, attr).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// `to`に入る辺の始点を返す。
+    pub fn predecessors(&self, to: usize) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter(|e| e.to == to)
+            .map(|e| e.from)
+            .collect()
+    }
+}
+
+/// プログラムの制御フローグラフを作る。
+///
+/// - `if`/`while`/`for`の条件は、分岐するブロックの末尾に置く
+/// - 条件が定数に畳み込める場合は、評価器と同じく実行されない側の辺を作らない
+/// - 条件を省略した`for`は、評価器と同じく本体を実行しない
+/// - `break`の後など、どこからも到達できない文は、入る辺のないブロックに置く
+/// - ブロック式の中の文は式の一部として扱い、ブロックに分けない
+///
+/// 言語に`continue`はないため、ループの本体から条件へ戻る辺は本体の末尾からのみ出る。
+pub fn build_cfg(program: &Program) -> Cfg {
+    let mut builder = Builder {
+        cfg: Cfg {
+            blocks: vec![BasicBlock::default(), BasicBlock::default()],
+            edges: vec![],
+        },
+        current: Some(Cfg::ENTRY),
+        loop_exits: vec![],
+    };
+    builder.stmts(&program.body);
+    builder.jump(Cfg::EXIT);
+    builder.cfg
+}
+
+struct Builder {
+    cfg: Cfg,
+    /// 文を追加しているブロック。`break`の直後など、到達できない位置では`None`
+    current: Option<usize>,
+    /// `break`で抜けた先のブロックを、外側のループから順に積む
+    loop_exits: Vec<usize>,
+}
+
+impl Builder {
+    fn new_block(&mut self) -> usize {
+        self.cfg.blocks.push(BasicBlock::default());
+        self.cfg.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: usize, to: usize, kind: EdgeKind) {
+        self.cfg.edges.push(Edge { from, to, kind });
+    }
+
+    /// 現在のブロックから`to`へ無条件に移る。到達できない位置では何もしない。
+    fn jump(&mut self, to: usize) {
+        if let Some(from) = self.current {
+            self.edge(from, to, EdgeKind::Always);
+        }
+    }
+
+    /// 現在のブロックに文を追加する。到達できない位置では、入る辺のないブロックを作る。
+    fn push(&mut self, stmt: String) -> usize {
+        let block = match self.current {
+            Some(block) => block,
+            None => self.new_block(),
+        };
+        self.cfg.blocks[block].stmts.push(stmt);
+        self.current = Some(block);
+        block
+    }
+
+    /// `head`の末尾で`cond`により分岐する。真の場合の移動先を返す。
+    ///
+    /// 定数に畳み込める条件では、実行されない側の辺を作らない。
+    fn branch(&mut self, head: usize, cond: Option<&Expression>, on_false: usize) -> usize {
+        let folded = match cond {
+            Some(cond) => const_value(cond).map(is_truthy),
+            None => Some(false),
+        };
+        let on_true = self.new_block();
+        if folded != Some(false) {
+            self.edge(head, on_true, EdgeKind::True);
+        }
+        if folded != Some(true) {
+            self.edge(head, on_false, EdgeKind::False);
+        }
+        on_true
+    }
+
+    fn stmts(&mut self, body: &[Statement]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::ExpressionStatement(_) | Statement::Empty => {
+                self.push(stmt.to_string());
+            }
+            Statement::BlockStatement(body) => self.stmts(body),
+            Statement::If(If { cond, then, r#else }) => {
+                let head = self.push(format!("if ({})", cond));
+                let merge = self.new_block();
+                let else_block = match r#else {
+                    Some(_) => self.new_block(),
+                    None => merge,
+                };
+
+                self.current = Some(self.branch(head, Some(cond), else_block));
+                self.stmts(then);
+                self.jump(merge);
+
+                if let Some(r#else) = r#else {
+                    self.current = Some(else_block);
+                    self.stmts(r#else);
+                    self.jump(merge);
+                }
+                self.current = Some(merge);
+            }
+            Statement::While(While { cond, body }) => {
+                let head = self.new_block();
+                self.jump(head);
+                self.cfg.blocks[head]
+                    .stmts
+                    .push(format!("while ({})", cond));
+                self.r#loop(head, Some(cond), body, head);
+            }
+            Statement::For(For {
+                init,
+                cond,
+                update,
+                body,
+            }) => {
+                if let Some(init) = init {
+                    self.push(format!("{};", init));
+                }
+                let head = self.new_block();
+                self.jump(head);
+                let cond_str = cond.as_ref().map(ToString::to_string).unwrap_or_default();
+                self.cfg.blocks[head]
+                    .stmts
+                    .push(format!("for ({})", cond_str));
+
+                // 本体の末尾から更新式を経て条件へ戻る。`break`では更新式を実行しない
+                let latch = match update {
+                    Some(update) => {
+                        let latch = self.new_block();
+                        self.cfg.blocks[latch].stmts.push(format!("{};", update));
+                        self.edge(latch, head, EdgeKind::Always);
+                        latch
+                    }
+                    None => head,
+                };
+                self.r#loop(head, cond.as_ref(), body, latch);
+            }
+            Statement::Break => {
+                let exit = *self.loop_exits.last().expect("break must be inside a loop");
+                self.push(stmt.to_string());
+                self.jump(exit);
+                self.current = None;
+            }
+        }
+    }
+
+    /// `head`で条件を判定し、本体の末尾から`back`へ戻るループを作る。
+    fn r#loop(&mut self, head: usize, cond: Option<&Expression>, body: &[Statement], back: usize) {
+        let exit = self.new_block();
+        self.current = Some(self.branch(head, cond, exit));
+        self.loop_exits.push(exit);
+        self.stmts(body);
+        self.loop_exits.pop();
+        self.jump(back);
+        self.current = Some(exit);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn cfg(input: &str) -> Cfg {
+        let tokens = Lexer::new(input).lex().unwrap();
+        build_cfg(&Parser::new(tokens).parse().unwrap())
+    }
+
+    fn edge(from: usize, to: usize, kind: EdgeKind) -> Edge {
+        Edge { from, to, kind }
+    }
+
+    #[test]
+    fn straight_line() {
+        let cfg = cfg("x = 1; x + 2;");
+        assert_eq!(cfg.blocks.len(), 2);
+        assert_eq!(cfg.blocks[Cfg::ENTRY].stmts, ["(x = 1);", "(x + 2);"]);
+        assert_eq!(cfg.edges, [edge(Cfg::ENTRY, Cfg::EXIT, EdgeKind::Always)]);
+    }
+
+    #[test]
+    fn if_else_merges() {
+        let cfg = cfg("x = 1; if (x) { y = 1; } else { y = 2; } y;");
+
+        // entry, exit, 合流, else, then
+        assert_eq!(cfg.blocks.len(), 5);
+        let (merge, r#else, then) = (2, 3, 4);
+        assert_eq!(cfg.blocks[Cfg::ENTRY].stmts, ["(x = 1);", "if (x)"]);
+        assert_eq!(cfg.blocks[merge].stmts, ["y;"]);
+        assert_eq!(
+            cfg.edges,
+            [
+                edge(Cfg::ENTRY, then, EdgeKind::True),
+                edge(Cfg::ENTRY, r#else, EdgeKind::False),
+                edge(then, merge, EdgeKind::Always),
+                edge(r#else, merge, EdgeKind::Always),
+                edge(merge, Cfg::EXIT, EdgeKind::Always),
+            ]
+        );
+    }
+
+    #[test]
+    fn if_without_else_falls_through() {
+        let cfg = cfg("if (x) { y = 1; }");
+        let (merge, then) = (2, 3);
+        let mut preds = cfg.predecessors(merge);
+        preds.sort();
+        assert_eq!(preds, [Cfg::ENTRY, then]);
+    }
+
+    #[test]
+    fn while_loop() {
+        let cfg = cfg("i = 0; while (i < 3) { i = i + 1; } i;");
+        let (head, exit, body) = (2, 3, 4);
+        assert_eq!(cfg.blocks[head].stmts, ["while ((i < 3))"]);
+        assert_eq!(
+            cfg.edges,
+            [
+                edge(Cfg::ENTRY, head, EdgeKind::Always),
+                edge(head, body, EdgeKind::True),
+                edge(head, exit, EdgeKind::False),
+                edge(body, head, EdgeKind::Always),
+                edge(exit, Cfg::EXIT, EdgeKind::Always),
+            ]
+        );
+    }
+
+    #[test]
+    fn break_skips_update() {
+        let cfg = cfg("for (i = 0; i < 10; i = i + 1) { if (i == 3) { break; } }");
+        let (head, latch, exit) = (2, 3, 4);
+        assert_eq!(cfg.blocks[latch].stmts, ["(i = (i + 1));"]);
+        // 条件が偽の場合と`break`の2箇所からループを抜ける
+        assert_eq!(cfg.predecessors(exit).len(), 2);
+        assert!(!cfg.predecessors(exit).contains(&latch));
+        assert_eq!(cfg.predecessors(head), [Cfg::ENTRY, latch]);
+    }
+
+    #[test]
+    fn unreachable_blocks() {
+        // `break`の後の文と、偽に畳み込める条件のループの本体には到達できない
+        let cfg = cfg("while (1) { break; x = 1; } while (0) { y = 1; }");
+        let unreachable: Vec<_> = (0..cfg.blocks.len())
+            .filter(|&b| b != Cfg::ENTRY && cfg.predecessors(b).is_empty())
+            .map(|b| cfg.blocks[b].stmts.clone())
+            .collect();
+        assert_eq!(unreachable, [vec!["(x = 1);"], vec!["(y = 1);"]]);
+    }
+
+    #[test]
+    fn dot_output() {
+        let cfg = cfg("x = 0; while (x < 2) { if (x == 1) { break; } x = x + 1; } x;");
+        insta::assert_snapshot!(cfg.to_dot());
+    }
+}
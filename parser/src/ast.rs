@@ -1,4 +1,9 @@
-use crate::token::TokenKind;
+use std::{fmt, str::FromStr};
+
+use crate::{
+    eval::{ArithMode, EvalResult, RuntimeError},
+    token::{Span, TokenKind},
+};
 
 pub mod prec {
     pub const LOWEST: u8 = 0;
@@ -10,14 +15,14 @@ pub mod prec {
     pub const POW: u8 = 6;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Assoc {
     Left,
     Right,
 }
 
 /// 演算子の優先度と結合順序を表す。
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct OpInfo {
     pub prec: u8,
     pub assoc: Assoc,
@@ -29,12 +34,14 @@ impl OpInfo {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum BinaryOp {
     Plus,
     Minus,
     Mul,
     Div,
+    /// 剰余。符号は左辺に従う(`-7 % 3`は`-1`)
+    Rem,
     Pow,
     Eq,
     Neq,
@@ -45,7 +52,7 @@ pub enum BinaryOp {
     Assign,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum UnaryOp {
     Minus,
 }
@@ -61,6 +68,7 @@ impl TryFrom<&TokenKind> for BinaryOp {
             Minus => Ok(BinaryOp::Minus),
             Mul => Ok(BinaryOp::Mul),
             Div => Ok(BinaryOp::Div),
+            Percent => Ok(BinaryOp::Rem),
             Pow => Ok(BinaryOp::Pow),
             Eq => Ok(BinaryOp::Eq),
             Neq => Ok(BinaryOp::Neq),
@@ -74,7 +82,128 @@ impl TryFrom<&TokenKind> for BinaryOp {
     }
 }
 
+impl FromStr for BinaryOp {
+    type Err = ();
+
+    /// [`BinaryOp::as_str`]で得られる名前から演算子を復元する。
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use BinaryOp::*;
+
+        match s {
+            "plus" => Ok(Plus),
+            "minus" => Ok(Minus),
+            "mul" => Ok(Mul),
+            "div" => Ok(Div),
+            "rem" => Ok(Rem),
+            "pow" => Ok(Pow),
+            "eq" => Ok(Eq),
+            "neq" => Ok(Neq),
+            "gt" => Ok(Gt),
+            "gteq" => Ok(GtEq),
+            "lt" => Ok(Lt),
+            "lteq" => Ok(LtEq),
+            "assign" => Ok(Assign),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 impl BinaryOp {
+    /// 設定ファイルやテストで演算子を参照するための名前を返す。
+    pub fn as_str(&self) -> &'static str {
+        use BinaryOp::*;
+
+        match self {
+            Plus => "plus",
+            Minus => "minus",
+            Mul => "mul",
+            Div => "div",
+            Rem => "rem",
+            Pow => "pow",
+            Eq => "eq",
+            Neq => "neq",
+            Gt => "gt",
+            GtEq => "gteq",
+            Lt => "lt",
+            LtEq => "lteq",
+            Assign => "assign",
+        }
+    }
+
+    /// ソースコード上の表記を返す。
+    pub fn symbol(&self) -> &'static str {
+        use BinaryOp::*;
+
+        match self {
+            Plus => "+",
+            Minus => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            Pow => "^",
+            Eq => "==",
+            Neq => "!=",
+            Gt => ">",
+            GtEq => ">=",
+            Lt => "<",
+            LtEq => "<=",
+            Assign => "=",
+        }
+    }
+
+    /// 演算子を値に適用する。オーバーフローは[`RuntimeError::Overflow`]になる。
+    ///
+    /// 演算子の意味はこのメソッドと[`BinaryOp::apply_with`]にのみ定義し、評価器などはこれを呼ぶ。
+    pub fn apply(&self, lhs: i32, rhs: i32) -> EvalResult<i32> {
+        self.apply_with(ArithMode::Checked, lhs, rhs)
+    }
+
+    /// [`ArithMode`]に従って演算子を値に適用する。
+    ///
+    /// - 比較演算は`1`(真)または`0`(偽)を返す
+    /// - 0除算は`mode`によらず[`RuntimeError::DivisionByZero`]になる
+    /// - 負の指数は`mode`によらず[`RuntimeError::NegativeExponent`]になる
+    ///
+    /// 代入は値の演算ではないため、呼び出し側で扱う。
+    pub fn apply_with(&self, mode: ArithMode, lhs: i32, rhs: i32) -> EvalResult<i32> {
+        use BinaryOp::*;
+
+        let (l, r) = (lhs, rhs);
+        match self {
+            Plus => mode.select(l.checked_add(r), l.wrapping_add(r)),
+            Minus => mode.select(l.checked_sub(r), l.wrapping_sub(r)),
+            Mul => mode.select(l.checked_mul(r), l.wrapping_mul(r)),
+            Div if r == 0 => Err(RuntimeError::DivisionByZero(format!("({} / {})", l, r))),
+            Div => mode.select(l.checked_div(r), l.wrapping_div(r)),
+            Rem if r == 0 => Err(RuntimeError::DivisionByZero(format!("({} % {})", l, r))),
+            Rem => mode.select(l.checked_rem(r), l.wrapping_rem(r)),
+            Pow => {
+                let exp = u32::try_from(r).map_err(|_| RuntimeError::NegativeExponent)?;
+                mode.select(l.checked_pow(exp), l.wrapping_pow(exp))
+            }
+            Eq => Ok((l == r) as i32),
+            Neq => Ok((l != r) as i32),
+            Gt => Ok((l > r) as i32),
+            GtEq => Ok((l >= r) as i32),
+            Lt => Ok((l < r) as i32),
+            LtEq => Ok((l <= r) as i32),
+            Assign => unreachable!("assignment is not a value operation"),
+        }
+    }
+
+    /// 結果が`1`(真)または`0`(偽)になる比較演算子か判定する。
+    pub fn is_comparison(&self) -> bool {
+        use BinaryOp::*;
+
+        matches!(self, Eq | Neq | Gt | GtEq | Lt | LtEq)
+    }
+
     pub fn op_info(&self) -> OpInfo {
         use BinaryOp::*;
 
@@ -87,7 +216,7 @@ impl BinaryOp {
                 prec: prec::PLUS,
                 assoc: Assoc::Left,
             },
-            Mul | Div => OpInfo {
+            Mul | Div | Rem => OpInfo {
                 prec: prec::MUL,
                 assoc: Assoc::Left,
             },
@@ -103,6 +232,83 @@ impl BinaryOp {
     }
 }
 
+impl UnaryOp {
+    /// ソースコード上の表記を返す。
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Minus => "-",
+        }
+    }
+
+    /// 演算子を値に適用する。オーバーフローは[`RuntimeError::Overflow`]になる。
+    pub fn apply(&self, v: i32) -> EvalResult<i32> {
+        self.apply_with(ArithMode::Checked, v)
+    }
+
+    /// [`ArithMode`]に従って演算子を値に適用する。
+    pub fn apply_with(&self, mode: ArithMode, v: i32) -> EvalResult<i32> {
+        match self {
+            UnaryOp::Minus => mode.select(v.checked_neg(), v.wrapping_neg()),
+        }
+    }
+}
+
+/// 組み込み関数。`abs(x)`のように関数呼び出しの形で書く。
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Builtin {
+    /// 絶対値
+    Abs,
+    /// 2つの値の小さい方
+    Min,
+    /// 2つの値の大きい方
+    Max,
+}
+
+impl Builtin {
+    /// 関数名から組み込み関数を探す。
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Self::Abs),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Abs => "abs",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+
+    /// 引数の数
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Abs => 1,
+            Self::Min | Self::Max => 2,
+        }
+    }
+
+    /// 関数を引数に適用する。オーバーフローは[`RuntimeError::Overflow`]になる。
+    pub fn apply(&self, args: &[i32]) -> EvalResult<i32> {
+        self.apply_with(ArithMode::Checked, args)
+    }
+
+    /// [`ArithMode`]に従って関数を引数に適用する。`abs(-2147483648)`のみオーバーフローしうる。
+    ///
+    /// 引数の数はパーサーが検査するため、[`Builtin::arity`]と異なる場合はpanicする。
+    pub fn apply_with(&self, mode: ArithMode, args: &[i32]) -> EvalResult<i32> {
+        match (self, args) {
+            (Self::Abs, &[x]) => mode.select(x.checked_abs(), x.wrapping_abs()),
+            (Self::Min, &[a, b]) => Ok(a.min(b)),
+            (Self::Max, &[a, b]) => Ok(a.max(b)),
+            _ => unreachable!("{} takes {} arguments", self.name(), self.arity()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Expression {
     Unary {
@@ -113,24 +319,114 @@ pub enum Expression {
         lhs: Box<Expression>,
         op: BinaryOp,
         rhs: Box<Expression>,
+        /// 左辺から右辺までのソース上の範囲
+        span: Span,
     },
     Value(i32),
     Var(String),
+    /// 組み込み関数の呼び出し。引数の数は[`Builtin::arity`]と一致する
+    Call {
+        func: Builtin,
+        args: Vec<Expression>,
+    },
+    /// `{ x = 1; x + 1 }`のように式の位置に書かれたブロック。
+    /// 末尾の`;`のない式があれば、その値がブロックの値になる
+    Block {
+        body: Vec<Statement>,
+        value: Option<Box<Expression>>,
+    },
 }
 
-#[derive(Debug)]
+/// 式の構造を比較する。ソース上の位置 (`span`) は比較しない。
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        use Expression::*;
+
+        match (self, other) {
+            (Unary { op: a, expr: x }, Unary { op: b, expr: y }) => a == b && x == y,
+            (
+                Binary {
+                    lhs: l1,
+                    op: o1,
+                    rhs: r1,
+                    ..
+                },
+                Binary {
+                    lhs: l2,
+                    op: o2,
+                    rhs: r2,
+                    ..
+                },
+            ) => o1 == o2 && l1 == l2 && r1 == r2,
+            (Value(a), Value(b)) => a == b,
+            (Var(a), Var(b)) => a == b,
+            (Call { func: f, args: a }, Call { func: g, args: b }) => f == g && a == b,
+            (
+                Block {
+                    body: b1,
+                    value: v1,
+                },
+                Block {
+                    body: b2,
+                    value: v2,
+                },
+            ) => b1 == b2 && v1 == v2,
+            _ => false,
+        }
+    }
+}
+
+/// 演算の構造が分かるよう、全ての演算を括弧で囲んで表示する。
+///
+/// 例: `1 + 2 * 3` は `(1 + (2 * 3))`
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Unary { op, expr } => write!(f, "({}{})", op.symbol(), expr),
+            Expression::Binary { lhs, op, rhs, .. } => {
+                write!(f, "({} {} {})", lhs, op.symbol(), rhs)
+            }
+            Expression::Value(n) => write!(f, "{}", n),
+            Expression::Var(name) => write!(f, "{}", name),
+            Expression::Call { func, args } => {
+                write!(f, "{}(", func.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Block { body, value } => {
+                write!(f, "{{")?;
+                for stmt in body {
+                    write!(f, " {}", stmt)?;
+                }
+                if let Some(value) = value {
+                    write!(f, " {}", value)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct If {
     pub cond: Expression,
     pub then: Vec<Statement>,
+    /// `else if`は、`If`文を1つだけ含む`else`として表現する
+    pub r#else: Option<Vec<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct While {
     pub cond: Expression,
     pub body: Vec<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct For {
     pub init: Option<Expression>,
     pub cond: Option<Expression>,
@@ -138,16 +434,237 @@ pub struct For {
     pub body: Vec<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Statement {
     ExpressionStatement(Expression),
     BlockStatement(Vec<Statement>),
     If(If),
     While(While),
     For(For),
+    /// 最も内側の`while`/`for`を抜ける。`for`の更新式は実行しない
+    Break,
+    /// `;`のみの文。何もせず、値は`0`になる
+    Empty,
+}
+
+/// 1行で表示する。式は[`Expression`]と同様に括弧で囲む。
+///
+/// 例: `if (x) { y = 1; }` は `if (x) { (y = 1); }`
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn block(f: &mut fmt::Formatter<'_>, body: &[Statement]) -> fmt::Result {
+            write!(f, "{{")?;
+            for stmt in body {
+                write!(f, " {}", stmt)?;
+            }
+            write!(f, " }}")
+        }
+        fn opt(e: &Option<Expression>) -> String {
+            e.as_ref().map(ToString::to_string).unwrap_or_default()
+        }
+
+        match self {
+            Statement::ExpressionStatement(expr) => write!(f, "{};", expr),
+            Statement::BlockStatement(body) => block(f, body),
+            Statement::If(If { cond, then, r#else }) => {
+                write!(f, "if ({}) ", cond)?;
+                block(f, then)?;
+                if let Some(r#else) = r#else {
+                    write!(f, " else ")?;
+                    block(f, r#else)?;
+                }
+                Ok(())
+            }
+            Statement::While(While { cond, body }) => {
+                write!(f, "while ({}) ", cond)?;
+                block(f, body)
+            }
+            Statement::For(For {
+                init,
+                cond,
+                update,
+                body,
+            }) => {
+                write!(f, "for ({}; {}; {}) ", opt(init), opt(cond), opt(update))?;
+                block(f, body)
+            }
+            Statement::Break => write!(f, "break;"),
+            Statement::Empty => write!(f, ";"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Program {
     pub body: Vec<Statement>,
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    const BINARY_OPS: &[BinaryOp] = &[
+        BinaryOp::Plus,
+        BinaryOp::Minus,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Rem,
+        BinaryOp::Pow,
+        BinaryOp::Eq,
+        BinaryOp::Neq,
+        BinaryOp::Gt,
+        BinaryOp::GtEq,
+        BinaryOp::Lt,
+        BinaryOp::LtEq,
+        BinaryOp::Assign,
+    ];
+
+    #[test]
+    fn binary_op_round_trip() {
+        for op in BINARY_OPS {
+            assert_eq!(op.to_string().parse::<BinaryOp>().as_ref(), Ok(op));
+        }
+    }
+
+    #[test]
+    fn binary_op_symbol() {
+        assert_eq!(BinaryOp::GtEq.symbol(), ">=");
+        assert_eq!(UnaryOp::Minus.symbol(), "-");
+    }
+
+    #[test]
+    fn binary_op_symbols_are_unique() {
+        let symbols: HashSet<_> = BINARY_OPS.iter().map(BinaryOp::symbol).collect();
+        assert_eq!(symbols.len(), BINARY_OPS.len());
+    }
+
+    #[test]
+    fn expression_display() {
+        let expr = Expression::Binary {
+            lhs: Box::new(Expression::Value(1)),
+            op: BinaryOp::Plus,
+            rhs: Box::new(Expression::Binary {
+                lhs: Box::new(Expression::Unary {
+                    op: UnaryOp::Minus,
+                    expr: Box::new(Expression::Var("x".to_string())),
+                }),
+                op: BinaryOp::GtEq,
+                rhs: Box::new(Expression::Value(3)),
+                span: Span::default(),
+            }),
+            span: Span::default(),
+        };
+        assert_eq!(expr.to_string(), "(1 + ((-x) >= 3))");
+    }
+
+    #[test]
+    fn block_display() {
+        let expr = Expression::Block {
+            body: vec![Statement::While(While {
+                cond: Expression::Var("x".to_string()),
+                body: vec![Statement::ExpressionStatement(Expression::Value(1))],
+            })],
+            value: Some(Box::new(Expression::Value(2))),
+        };
+        assert_eq!(expr.to_string(), "{ while (x) { 1; } 2 }");
+    }
+
+    #[test]
+    fn binary_ops_as_keys() {
+        let names: HashMap<_, _> = BINARY_OPS.iter().map(|op| (op, op.as_str())).collect();
+        assert_eq!(names.len(), BINARY_OPS.len());
+        assert_eq!(names[&BinaryOp::Pow], "pow");
+    }
+
+    #[test]
+    fn binary_op_from_str() {
+        assert_eq!("plus".parse::<BinaryOp>(), Ok(BinaryOp::Plus));
+        assert_eq!("+".parse::<BinaryOp>(), Err(()));
+        assert_eq!("unknown".parse::<BinaryOp>(), Err(()));
+    }
+
+    #[test]
+    fn binary_op_apply() {
+        use BinaryOp::*;
+
+        #[rustfmt::skip]
+        let cases = [
+            (Plus,  7, 3, 10),
+            (Minus, 7, 3, 4),
+            (Mul,   7, 3, 21),
+            (Div,   7, 3, 2),
+            (Div,  -7, 2, -3),
+            (Rem,   7, 3, 1),
+            (Rem,  -7, 3, -1),
+            (Rem,   7, -3, 1),
+            (Pow,   2, 10, 1024),
+            (Pow,   5, 0, 1),
+            (Eq,    3, 3, 1),
+            (Neq,   3, 3, 0),
+            (Gt,    3, 2, 1),
+            (GtEq,  2, 3, 0),
+            (Lt,    2, 3, 1),
+            (LtEq,  3, 3, 1),
+        ];
+        for (op, l, r, expected) in cases {
+            assert_eq!(op.apply(l, r), Ok(expected), "{} {} {}", l, op.symbol(), r);
+        }
+    }
+
+    #[test]
+    fn binary_op_apply_errors() {
+        use BinaryOp::*;
+
+        assert_eq!(Plus.apply(i32::MAX, 1), Err(RuntimeError::Overflow));
+        assert_eq!(Minus.apply(i32::MIN, 1), Err(RuntimeError::Overflow));
+        assert_eq!(Mul.apply(i32::MAX, 2), Err(RuntimeError::Overflow));
+        assert_eq!(Div.apply(i32::MIN, -1), Err(RuntimeError::Overflow));
+        assert_eq!(Pow.apply(2, 31), Err(RuntimeError::Overflow));
+        assert_eq!(
+            Div.apply(1, 0),
+            Err(RuntimeError::DivisionByZero("(1 / 0)".to_string()))
+        );
+        assert_eq!(
+            Rem.apply(1, 0),
+            Err(RuntimeError::DivisionByZero("(1 % 0)".to_string()))
+        );
+        assert_eq!(Rem.apply(i32::MIN, -1), Err(RuntimeError::Overflow));
+        assert_eq!(Pow.apply(2, -1), Err(RuntimeError::NegativeExponent));
+
+        // Wrappingでもオーバーフロー以外のエラーは変わらない
+        let wrapping = ArithMode::Wrapping;
+        assert_eq!(Plus.apply_with(wrapping, i32::MAX, 1), Ok(i32::MIN));
+        assert_eq!(Div.apply_with(wrapping, i32::MIN, -1), Ok(i32::MIN));
+        assert!(Div.apply_with(wrapping, 1, 0).is_err());
+        assert_eq!(Rem.apply_with(wrapping, i32::MIN, -1), Ok(0));
+        assert!(Rem.apply_with(wrapping, 1, 0).is_err());
+        assert!(Pow.apply_with(wrapping, 2, -1).is_err());
+    }
+
+    #[test]
+    fn unary_op_apply() {
+        assert_eq!(UnaryOp::Minus.apply(5), Ok(-5));
+        assert_eq!(UnaryOp::Minus.apply(i32::MIN), Err(RuntimeError::Overflow));
+        assert_eq!(
+            UnaryOp::Minus.apply_with(ArithMode::Wrapping, i32::MIN),
+            Ok(i32::MIN)
+        );
+    }
+
+    #[test]
+    fn builtin_apply() {
+        assert_eq!(Builtin::from_name("abs"), Some(Builtin::Abs));
+        assert_eq!(Builtin::from_name("pow"), None);
+        assert_eq!(Builtin::Abs.apply(&[-5]), Ok(5));
+        assert_eq!(Builtin::Min.apply(&[3, -7]), Ok(-7));
+        assert_eq!(Builtin::Max.apply(&[3, 7]), Ok(7));
+        assert_eq!(Builtin::Abs.apply(&[i32::MIN]), Err(RuntimeError::Overflow));
+        assert_eq!(
+            Builtin::Abs.apply_with(ArithMode::Wrapping, &[i32::MIN]),
+            Ok(i32::MIN)
+        );
+    }
+}
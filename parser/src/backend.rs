@@ -0,0 +1,335 @@
+use std::str::FromStr;
+
+use crate::{
+    ast::{BinaryOp, Builtin, Expression, For, If, Program, Statement, UnaryOp, While},
+    codegen::CodeGenerator,
+    visit::variables,
+};
+
+/// [`Program`]をソースコードに変換するバックエンド。
+pub trait Backend {
+    /// 出力するソースファイルの拡張子
+    fn extension(&self) -> &'static str;
+
+    fn emit(&mut self, program: &Program) -> String;
+}
+
+/// `--backend`で選択できるバックエンドの種類。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    /// AArch64アセンブリを出力する[`CodeGenerator`]
+    #[default]
+    Arm64,
+    /// Cのソースコードを出力する[`CBackend`]
+    C,
+}
+
+impl FromStr for BackendKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arm64" => Ok(Self::Arm64),
+            "c" => Ok(Self::C),
+            _ => Err(()),
+        }
+    }
+}
+
+impl BackendKind {
+    pub fn backend(self) -> Box<dyn Backend> {
+        match self {
+            Self::Arm64 => Box::new(CodeGenerator::new()),
+            Self::C => Box::new(CBackend),
+        }
+    }
+}
+
+impl Backend for CodeGenerator {
+    fn extension(&self) -> &'static str {
+        "s"
+    }
+
+    fn emit(&mut self, program: &Program) -> String {
+        self.generate(program)
+    }
+}
+
+/// Cのソースコードを出力するバックエンド。`cc`さえあればホストによらずビルドできる。
+///
+/// プログラムの値(最後の文の値)を`main`の戻り値とする。文の値は評価器と同じ規則で求める。
+///
+/// 評価器との違い:
+/// - 算術演算は[`ArithMode::Wrapping`](crate::eval::ArithMode::Wrapping)と同じく折り返す
+/// - 0除算と負の指数は検査しない
+/// - 未定義の変数は`0`として読む
+/// - 二項演算の左右や関数の引数の評価順序はCと同じく規定しない
+/// - ブロック式にはGCC/Clangのstatement expression(`({ ... })`)を使う
+#[derive(Debug, Default)]
+pub struct CBackend;
+
+/// 符号付き整数のオーバーフローは未定義動作のため、符号なし整数で計算して折り返す
+const PRELUDE: &str = "\
+static int add(int a, int b) { return (int)((unsigned)a + (unsigned)b); }
+static int sub(int a, int b) { return (int)((unsigned)a - (unsigned)b); }
+static int mul(int a, int b) { return (int)((unsigned)a * (unsigned)b); }
+static int ipow(int b, int e) {
+    unsigned r = 1;
+    while (e-- > 0) r *= (unsigned)b;
+    return (int)r;
+}
+static int iabs(int a) { return a < 0 ? sub(0, a) : a; }
+static int imin(int a, int b) { return a < b ? a : b; }
+static int imax(int a, int b) { return a > b ? a : b; }
+";
+
+/// 直前に評価した文の値を保持するCの変数
+const LAST_VALUE: &str = "_v";
+
+impl Backend for CBackend {
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn emit(&mut self, program: &Program) -> String {
+        let mut output = String::from(PRELUDE);
+        output.push_str("\nint main(void) {\n");
+        output.push_str(&format!("    int {} = 0;\n", LAST_VALUE));
+        for name in &variables(program) {
+            output.push_str(&format!("    int {} = 0;\n", mangle(name)));
+        }
+        for s in &program.body {
+            output.push_str(&stmt(s, 1));
+        }
+        output.push_str(&format!("    return {};\n}}\n", LAST_VALUE));
+        output
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth)
+}
+
+/// 識別子をCの識別子に変換する。
+///
+/// ソース上の識別子は英数字のみからなるため、`v_`を付け、
+/// ASCII以外の文字を`_uXXXX_`に置き換えれば衝突しない。
+fn mangle(name: &str) -> String {
+    let mut mangled = String::from("v_");
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            mangled.push(c);
+        } else {
+            mangled.push_str(&format!("_u{:x}_", c as u32));
+        }
+    }
+    mangled
+}
+
+/// 本体の前に文の値を`0`に戻し、空のブロックの値を`0`にする。
+fn block(body: &[Statement], depth: usize) -> String {
+    let mut output = format!("{{\n{}{} = 0;\n", indent(depth + 1), LAST_VALUE);
+    for s in body {
+        output.push_str(&stmt(s, depth + 1));
+    }
+    output.push_str(&indent(depth));
+    output.push('}');
+    output
+}
+
+fn stmt(stmt: &Statement, depth: usize) -> String {
+    let ind = indent(depth);
+    match stmt {
+        Statement::ExpressionStatement(e) => {
+            format!("{}{} = {};\n", ind, LAST_VALUE, expr(e, depth))
+        }
+        Statement::BlockStatement(body) => format!("{}{}\n", ind, block(body, depth)),
+        Statement::If(If { cond, then, r#else }) => {
+            // elseがない場合も、条件が偽なら文の値を0にするためにelseを出力する
+            format!(
+                "{}if ({} > 0) {} else {}\n",
+                ind,
+                expr(cond, depth),
+                block(then, depth),
+                block(r#else.as_deref().unwrap_or_default(), depth)
+            )
+        }
+        Statement::While(While { cond, body }) => format!(
+            "{}while ({} > 0) {}\n{}{} = 0;\n",
+            ind,
+            expr(cond, depth),
+            block(body, depth),
+            ind,
+            LAST_VALUE
+        ),
+        Statement::For(For {
+            init,
+            cond,
+            update,
+            body,
+        }) => {
+            let opt =
+                |e: &Option<Expression>| e.as_ref().map(|e| expr(e, depth)).unwrap_or_default();
+            // 条件を省略したforは本体を実行しない
+            let cond = cond
+                .as_ref()
+                .map_or("0".to_string(), |c| format!("{} > 0", expr(c, depth)));
+            format!(
+                "{}for ({}; {}; {}) {}\n{}{} = 0;\n",
+                ind,
+                opt(init),
+                cond,
+                opt(update),
+                block(body, depth),
+                ind,
+                LAST_VALUE
+            )
+        }
+        Statement::Break => format!("{}break;\n", ind),
+        Statement::Empty => format!("{}{} = 0;\n", ind, LAST_VALUE),
+    }
+}
+
+fn expr(e: &Expression, depth: usize) -> String {
+    match e {
+        Expression::Value(n) => n.to_string(),
+        Expression::Var(name) => mangle(name),
+        Expression::Unary { op, expr: e } => match op {
+            UnaryOp::Minus => format!("sub(0, {})", expr(e, depth)),
+        },
+        Expression::Binary { lhs, op, rhs, .. } => {
+            let (l, r) = (expr(lhs, depth), expr(rhs, depth));
+            match op {
+                BinaryOp::Plus => format!("add({}, {})", l, r),
+                BinaryOp::Minus => format!("sub({}, {})", l, r),
+                BinaryOp::Mul => format!("mul({}, {})", l, r),
+                BinaryOp::Pow => format!("ipow({}, {})", l, r),
+                BinaryOp::Div
+                | BinaryOp::Rem
+                | BinaryOp::Eq
+                | BinaryOp::Neq
+                | BinaryOp::Gt
+                | BinaryOp::GtEq
+                | BinaryOp::Lt
+                | BinaryOp::LtEq
+                | BinaryOp::Assign => format!("({} {} {})", l, op.symbol(), r),
+            }
+        }
+        Expression::Call { func, args } => {
+            let name = match func {
+                Builtin::Abs => "iabs",
+                Builtin::Min => "imin",
+                Builtin::Max => "imax",
+            };
+            let args = args
+                .iter()
+                .map(|arg| expr(arg, depth))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}({})", name, args)
+        }
+        Expression::Block { body, value } => {
+            let mut output = String::from("({\n");
+            for s in body {
+                output.push_str(&stmt(s, depth + 1));
+            }
+            let value = value
+                .as_ref()
+                .map_or("0".to_string(), |v| expr(v, depth + 1));
+            output.push_str(&format!(
+                "{}{};\n{}}})",
+                indent(depth + 1),
+                value,
+                indent(depth)
+            ));
+            output
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process::Command};
+
+    use super::*;
+    use crate::{eval::calc, lexer::Lexer, parser::Parser};
+
+    fn parse(input: &str) -> Program {
+        let tokens = Lexer::new(input).lex().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    /// Cのソースをコンパイルして実行し、終了コードを返す。
+    fn compile_and_run(name: &str, source: &str) -> i32 {
+        let dir = env::temp_dir().join(format!("parser-c-backend-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let c_file = dir.join(format!("{}.c", name));
+        let exe = dir.join(name);
+        fs::write(&c_file, source).unwrap();
+
+        let output = Command::new("cc")
+            .arg("-o")
+            .arg(&exe)
+            .arg(&c_file)
+            .output()
+            .expect("failed to execute cc");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        Command::new(&exe).status().unwrap().code().unwrap()
+    }
+
+    #[test]
+    fn backend_kind_from_str() {
+        assert_eq!("arm64".parse(), Ok(BackendKind::Arm64));
+        assert_eq!("c".parse(), Ok(BackendKind::C));
+        assert_eq!("x86".parse::<BackendKind>(), Err(()));
+    }
+
+    #[test]
+    fn mangle_identifiers() {
+        assert_eq!(mangle("x1"), "v_x1");
+        assert_eq!(mangle("変数"), "v__u5909__u6570_");
+    }
+
+    #[test]
+    fn c_output() {
+        let program = parse("x = 1; if (x) { x = -x + 2 ^ 3; } else { x = x / 2; } x;");
+        insta::assert_snapshot!(CBackend.emit(&program));
+    }
+
+    /// 終了コードに収まる値になるプログラムを、評価器と同じ値を返すか確かめる
+    #[test]
+    fn c_matches_evaluator() {
+        #[rustfmt::skip]
+        const TESTS: &[(&str, &str)] = &[
+            ("arith",          "1 + 2 * 3 - 8 / 4;"),
+            ("unary",          "-3 + 10;"),
+            ("power",          "2 ^ 3 ^ 2 - 500;"),
+            ("compare",        "(3 > 2) + (2 >= 2) + (1 == 2) + (1 != 2) + (1 < 2) + (2 <= 1);"),
+            ("if_else",        "x = 3; if (x > 5) { 1; } else if (x > 2) { 2; } else { 3; }"),
+            ("if_skipped",     "7; if (0) { 1; }"),
+            ("if_negative",    "x = 0; if (-1) { x = 1; } else { x = 2; } x;"),
+            ("while_negative", "x = -3; while (x) { x = 5; } x + 3;"),
+            ("while",          "i = 0; while (i < 10) { i = i + 3; } i;"),
+            ("while_last",     "i = 0; while (i < 3) { i = i + 1; }"),
+            ("fibonacci",      "n=10; a=0; b=1; for (i=0; i<n; i=i+1) { tmp = a; a = b; b = tmp + b;} a;"),
+            ("empty_for",      "x = 1; for (x = 2;;) { x = 3; } x;"),
+            ("for_break",      "for (i=0; i<10; i=i+1) { if (i==3) { break; } } i;"),
+            ("block",          "y = { x = 4; x * 2 }; y + { 1; };"),
+            ("builtins",       "x = -5; abs(x) + max(3, 7) + min(x, 2);"),
+            ("multibyte",      "変数 = 5; 変数 * 2;"),
+            ("rem",            "x = -7; x % 3 + 10 % 4 + 2 + 6 % 4;"),
+            ("empty",          "x = 4; { x = x + 1; };"),
+        ];
+
+        for (name, input) in TESTS {
+            let expected = calc(input).unwrap();
+            let source = CBackend.emit(&parse(input));
+            assert_eq!(compile_and_run(name, &source), expected, "{}", input);
+        }
+    }
+}
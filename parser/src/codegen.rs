@@ -1,31 +1,84 @@
+use std::collections::HashMap;
+
 use crate::{
     ast,
-    ast::{BinaryOp, Expression, Program, Statement, UnaryOp},
+    ast::{BinaryOp, Builtin, Expression, Program, Statement, UnaryOp},
+    visit::variables,
 };
 
+/// 変数1つあたりのバイト数
+const SLOT_SIZE: usize = 8;
+
+/// AArch64アセンブリを生成する。
+///
+/// ## スタックレイアウト
+///
+/// ```text
+///          +-----------------+
+///  x29 ->  | 保存したx29/x30 |
+///          +-----------------+ <- x29 - 0
+///          | 変数0           |    [x29, #-8]
+///          | 変数1           |    [x29, #-16]
+///          | ...             |
+///          +-----------------+ <- x29 - frame_size (16バイト境界)
+///          | オペランド      |    str x0, [sp, #-16]! で積む
+///          | ...             |
+///  sp  ->  +-----------------+
+/// ```
+///
+/// 変数はフレームポインタ`x29`からの負のオフセットに置き、
+/// 式の評価に使うSP相対のオペランドスタックとは領域を分ける。
+#[derive(Default)]
 pub struct CodeGenerator {
     output: Vec<String>,
+    /// 変数名から`x29`からのオフセット(正の値)への対応
+    slots: HashMap<String, usize>,
 }
 
 impl CodeGenerator {
     pub fn new() -> Self {
-        Self { output: vec![] }
+        Self::default()
     }
 
     pub fn generate(&mut self, program: &Program) -> String {
+        let names = variables(program);
+        self.slots = names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, (i + 1) * SLOT_SIZE))
+            .collect();
+
         self.output.push("    .globl _main".to_string());
         self.output.push("_main:".to_string());
 
+        // プロローグ: SPを16バイト境界に保ったまま、変数の領域を確保する
+        self.output
+            .push("    stp x29, x30, [sp, #-16]!".to_string());
+        self.output.push("    mov x29, sp".to_string());
+        let frame_size = frame_size(self.slots.len());
+        if frame_size > 0 {
+            self.output.push(format!("    sub sp, sp, #{}", frame_size));
+        }
+
+        // 最後に評価した式文の値がx0に残り、戻り値になる
         for stmt in &program.body {
             self.stmt(stmt);
         }
 
-        self.output.push("    ldr x0, [sp], #16".to_string());
+        // エピローグ: 変数の領域とオペランドスタックをまとめて解放する
+        self.output.push("    mov sp, x29".to_string());
+        self.output.push("    ldp x29, x30, [sp], #16".to_string());
         self.output.push("    ret".to_string());
 
         self.print()
     }
 
+    /// 変数の`x29`からのオフセットを返す。
+    fn slot(&self, name: &str) -> usize {
+        // 全ての変数はgenerateの開始時に割り当て済み
+        self.slots[name]
+    }
+
     fn print(&self) -> String {
         self.output.join("\n")
     }
@@ -36,7 +89,7 @@ impl CodeGenerator {
                 self.expr(expr);
                 self.output.push("    ldr x0, [sp], #16".to_string());
             }
-            Statement::If(ast::If { cond, then }) => {
+            Statement::If(ast::If { cond, then, r#else }) => {
                 self.expr(cond);
 
                 // 1. cmpで比較
@@ -44,20 +97,35 @@ impl CodeGenerator {
                 //   false: .LelseXXXにジャンプ
                 // 2. trueの末尾で、.LendXXXにジャンプ
                 self.output.push("    ldr x0, [sp], #16".to_string());
-                // truthy判定の実装を簡単にするため、x0が0の場合、else文にジャンプしている
+                // 評価器と同じく0より大きい値を真とするため、x0 <= 0(符号付き)の場合にelse文へジャンプする。
+                // 比較演算の結果は1/0なので、どちらの規則でも同じになる
                 self.output.push("    cmp x0, #0".to_string());
-                self.output.push("    b.eq .LelseXXX".to_string());
+                self.output.push("    b.le .LelseXXX".to_string());
 
                 for s in then {
                     self.stmt(s);
                 }
                 self.output.push("    b .LendXXX".to_string());
                 self.output.push(".LelseXXX:".to_string());
+                for s in r#else.iter().flatten() {
+                    self.stmt(s);
+                }
                 self.output.push(".LendXXX:".to_string());
             }
-            Statement::BlockStatement(_) => unimplemented!(),
+            Statement::BlockStatement(body) => {
+                // 評価器と同じく、空のブロックの値を0にする
+                if body.is_empty() {
+                    self.output.push("    mov x0, #0".to_string());
+                }
+                for s in body {
+                    self.stmt(s);
+                }
+            }
             Statement::While(_) => unimplemented!(),
-            Statement::For(_) => unimplemented!(),
+            Statement::For(_) => unreachable!("for statements are desugared into while"),
+            Statement::Break => unimplemented!(),
+            // 評価器と同じく、空文の値を0にする
+            Statement::Empty => self.output.push("    mov x0, #0".to_string()),
         };
     }
 
@@ -71,7 +139,7 @@ impl CodeGenerator {
                     self.output.push("    str x0, [sp, #-16]!".to_string())
                 }
             },
-            Expression::Binary { lhs, op, rhs } => match op {
+            Expression::Binary { lhs, op, rhs, .. } => match op {
                 BinaryOp::Plus => {
                     self.expr(lhs);
                     self.expr(rhs);
@@ -105,6 +173,16 @@ impl CodeGenerator {
                     self.output.push("    sdiv x0, x0, x1".to_string());
                     self.output.push("    str x0, [sp, #-16]!".to_string());
                 }
+                BinaryOp::Rem => {
+                    self.expr(lhs);
+                    self.expr(rhs);
+                    self.output.push("    ldr x1, [sp], #16".to_string());
+                    self.output.push("    ldr x0, [sp], #16".to_string());
+                    // x0 - (x0 / x1) * x1。sdivと同じく、ゼロ除算では左辺がそのまま残る
+                    self.output.push("    sdiv x2, x0, x1".to_string());
+                    self.output.push("    msub x0, x2, x1, x0".to_string());
+                    self.output.push("    str x0, [sp, #-16]!".to_string());
+                }
                 BinaryOp::Pow => {
                     self.expr(lhs);
                     self.expr(rhs);
@@ -184,16 +262,171 @@ impl CodeGenerator {
                     self.output.push("    str x0, [sp, #-16]!".to_string());
                 }
                 BinaryOp::Assign => {
-                    unimplemented!();
+                    // 代入先が変数であることはパーサーが保証している
+                    let Expression::Var(name) = lhs.as_ref() else {
+                        unreachable!("assignment target must be a variable");
+                    };
+                    self.expr(rhs);
+                    // 代入式の値として、右辺の値はスタックに残す
+                    self.output.push("    ldr x0, [sp]".to_string());
+                    self.output
+                        .push(format!("    str x0, [x29, #-{}]", self.slot(name)));
                 }
             },
             Expression::Value(n) => {
                 self.output.push(format!("    mov x0, #{}", n));
                 self.output.push("    str x0, [sp, #-16]!".to_string());
             }
-            Expression::Var(_name) => {
-                unimplemented!();
+            Expression::Var(name) => {
+                self.output
+                    .push(format!("    ldr x0, [x29, #-{}]", self.slot(name)));
+                self.output.push("    str x0, [sp, #-16]!".to_string());
+            }
+            Expression::Call { func, args } => {
+                for arg in args {
+                    self.expr(arg);
+                }
+                match func {
+                    Builtin::Abs => {
+                        self.output.push("    ldr x0, [sp], #16".to_string());
+                        self.output.push("    cmp x0, #0".to_string());
+                        self.output
+                            .push("    cneg x0, x0, lt  ; x0 = -x0 if x0 < 0".to_string());
+                    }
+                    Builtin::Min => {
+                        self.output.push("    ldr x1, [sp], #16".to_string());
+                        self.output.push("    ldr x0, [sp], #16".to_string());
+                        self.output.push("    cmp x0, x1".to_string());
+                        self.output
+                            .push("    csel x0, x0, x1, lt  ; x0 = min(x0, x1)".to_string());
+                    }
+                    Builtin::Max => {
+                        self.output.push("    ldr x1, [sp], #16".to_string());
+                        self.output.push("    ldr x0, [sp], #16".to_string());
+                        self.output.push("    cmp x0, x1".to_string());
+                        self.output
+                            .push("    csel x0, x0, x1, gt  ; x0 = max(x0, x1)".to_string());
+                    }
+                }
+                self.output.push("    str x0, [sp, #-16]!".to_string());
+            }
+            Expression::Block { body, value } => {
+                // 文はそれぞれオペランドスタックを元に戻すため、ブロックの値だけが積まれる
+                for stmt in body {
+                    self.stmt(stmt);
+                }
+                match value {
+                    Some(value) => self.expr(value),
+                    // 評価器と同じく、末尾が`;`で終わるブロックの値は0
+                    None => {
+                        self.output.push("    mov x0, #0".to_string());
+                        self.output.push("    str x0, [sp, #-16]!".to_string());
+                    }
+                }
             }
         };
     }
 }
+
+/// `vars`個の変数を格納するのに必要な、16バイト境界に揃えたバイト数を返す。
+fn frame_size(vars: usize) -> usize {
+    (vars * SLOT_SIZE).next_multiple_of(16)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn generate(source: &str) -> String {
+        let tokens = Lexer::new(source).lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let assembly = CodeGenerator::new().generate(&program);
+
+        format!("source: {}\n\n{}\n", source, assembly)
+    }
+
+    #[test]
+    fn codegen() {
+        #[rustfmt::skip]
+        const TESTS: &[(&str, &str)] = &[
+            ("plus",        "1+2;"),
+            ("minus",       "5-3;"),
+            ("mul_div",     "6*4/2;"),
+            ("rem",         "7%3;"),
+            ("unary_minus", "-5;"),
+            ("power",       "10^2;"),
+            ("eq",          "1==1;"),
+            ("neq",         "1!=2;"),
+            ("gt",          "2>1;"),
+            ("gt_eq",       "2>=1;"),
+            ("lt",          "1<2;"),
+            ("lt_eq",       "1<=2;"),
+            ("if_else",     "if (1) {2;} else {3;}"),
+            ("if_negative", "x = 0; if (-1) {x = 1;} else {x = 2;} x;"),
+            ("variables",   "a = 2; b = 3; c = a * b + 1; c;"),
+            ("builtins",    "x = -5; max(abs(x), 3) + min(1, 2);"),
+            ("block",       "x = 1; { x = x + 1; x * 2; }"),
+            ("block_expr",  "y = { x = 1; x + 1 }; y + { 3; };"),
+        ];
+
+        for (name, source) in TESTS {
+            insta::assert_snapshot!(*name, generate(source));
+        }
+    }
+
+    #[test]
+    fn frame_size_is_aligned() {
+        assert_eq!(frame_size(0), 0);
+        assert_eq!(frame_size(1), 16);
+        assert_eq!(frame_size(2), 16);
+        assert_eq!(frame_size(3), 32);
+    }
+
+    /// 生成したアセンブリをビルドして実行し、評価器と同じ値を返すか確かめる。
+    /// `_main`はmacOSの呼び出し規約のため、Apple Siliconでのみ実行する
+    #[cfg(all(target_arch = "aarch64", target_os = "macos"))]
+    #[test]
+    fn run_matches_evaluator() {
+        use std::{env, fs, process::Command};
+
+        use crate::eval::calc;
+
+        #[rustfmt::skip]
+        const TESTS: &[(&str, &str)] = &[
+            ("variables",   "a = 2; b = 3; c = a * b + 1; c;"),
+            // 負の値は偽、比較の結果の1は真
+            ("if_negative", "x = 0; if (-1) {x = 1;} else {x = 2;} x;"),
+            ("if_compare",  "x = 0; if (2 > 1) {x = 1;} else {x = 2;} x;"),
+            ("builtins",    "x = -5; max(abs(x), 3) + min(1, 2);"),
+            ("rem",         "x = -7; x % 3 + 10 % 4;"),
+            ("block",       "x = 1; { x = x + 1; x * 2; }"),
+            ("empty_block", "1; {}"),
+            ("block_expr",  "y = { x = 1; x + 1 }; y + { 3; };"),
+        ];
+
+        let dir = env::temp_dir().join(format!("parser-codegen-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (name, source) in TESTS {
+            let tokens = Lexer::new(source).lex().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            let assembly = CodeGenerator::new().generate(&program);
+
+            let asm = dir.join(format!("{}.s", name));
+            let exe = dir.join(name);
+            fs::write(&asm, assembly + "\n").unwrap();
+
+            let status = Command::new("cc")
+                .arg("-o")
+                .arg(&exe)
+                .arg(&asm)
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            let code = Command::new(&exe).status().unwrap().code();
+            assert_eq!(code, Some(calc(source).unwrap()), "{}", source);
+        }
+    }
+}
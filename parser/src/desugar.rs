@@ -0,0 +1,151 @@
+use crate::ast::{Expression, For, If, Program, Statement, While};
+
+/// `for`文を`while`文に書き換え、コード生成が扱う制御構文を減らす。
+///
+/// `for (init; cond; update) { body }`は次のように変換する。
+///
+/// ```text
+/// { init; while (cond) { body; update; } }
+/// ```
+///
+/// 条件を省略した`for`は本体を実行しない(評価器の意味論と同じ)ため、
+/// `cond`には`0`を補う。`update`は本体の末尾に置くため、`break`した場合は実行されない。
+pub fn desugar(program: Program) -> Program {
+    Program {
+        body: program.body.into_iter().map(stmt).collect(),
+    }
+}
+
+fn block(body: Vec<Statement>) -> Vec<Statement> {
+    body.into_iter().map(stmt).collect()
+}
+
+fn stmt(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::ExpressionStatement(e) => Statement::ExpressionStatement(expr(e)),
+        Statement::BlockStatement(body) => Statement::BlockStatement(block(body)),
+        Statement::If(If { cond, then, r#else }) => Statement::If(If {
+            cond: expr(cond),
+            then: block(then),
+            r#else: r#else.map(block),
+        }),
+        Statement::While(While { cond, body }) => Statement::While(While {
+            cond: expr(cond),
+            body: block(body),
+        }),
+        Statement::For(For {
+            init,
+            cond,
+            update,
+            body,
+        }) => {
+            let mut body = block(body);
+            body.extend(update.map(|e| Statement::ExpressionStatement(expr(e))));
+
+            let mut stmts: Vec<_> = init
+                .map(|e| Statement::ExpressionStatement(expr(e)))
+                .into_iter()
+                .collect();
+            stmts.push(Statement::While(While {
+                cond: cond.map_or(Expression::Value(0), expr),
+                body,
+            }));
+            Statement::BlockStatement(stmts)
+        }
+        Statement::Break => Statement::Break,
+        Statement::Empty => Statement::Empty,
+    }
+}
+
+/// ブロック式の中の`for`文も変換する。
+fn expr(expr: Expression) -> Expression {
+    match expr {
+        Expression::Unary { op, expr } => Expression::Unary {
+            op,
+            expr: Box::new(self::expr(*expr)),
+        },
+        Expression::Binary { lhs, op, rhs, span } => Expression::Binary {
+            lhs: Box::new(self::expr(*lhs)),
+            op,
+            rhs: Box::new(self::expr(*rhs)),
+            span,
+        },
+        Expression::Value(_) | Expression::Var(_) => expr,
+        Expression::Call { func, args } => Expression::Call {
+            func,
+            args: args.into_iter().map(self::expr).collect(),
+        },
+        Expression::Block { body, value } => Expression::Block {
+            body: block(body),
+            value: value.map(|e| Box::new(self::expr(*e))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::Lexer, parser::Parser};
+
+    fn parse(input: &str) -> Program {
+        let tokens = Lexer::new(input).lex().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    /// 変換前後のプログラムを評価し、値が一致することを確かめる
+    fn assert_same_value(input: &str, expected: i32) {
+        let original = Evaluator::new().eval(&parse(input));
+        let desugared = Evaluator::new().eval(&desugar(parse(input)));
+
+        assert_eq!(original, Ok(expected), "{}", input);
+        assert_eq!(desugared, original, "{}", input);
+    }
+
+    #[test]
+    fn for_becomes_while() {
+        let program = desugar(parse("for (i = 0; i < 3; i = i + 1) { x = i; }"));
+
+        let [Statement::BlockStatement(stmts)] = program.body.as_slice() else {
+            panic!("expected a single block, got {:?}", program.body);
+        };
+        let [
+            Statement::ExpressionStatement(init),
+            Statement::While(While { cond, body }),
+        ] = stmts.as_slice()
+        else {
+            panic!("expected init and while, got {:?}", stmts);
+        };
+        assert_eq!(init.to_string(), "(i = 0)");
+        assert_eq!(cond.to_string(), "(i < 3)");
+        assert_eq!(body.len(), 2);
+    }
+
+    #[test]
+    fn fibonacci() {
+        assert_same_value(
+            "n=10; a=0; b=1; for (i=0; i<n; i=i+1) { tmp = a; a = b; b = tmp + b;} a;",
+            55,
+        );
+    }
+
+    #[test]
+    fn counter() {
+        assert_same_value("x = 0; for (i = 0; i < 5; i = i + 1) { x = x + 2; } x;", 10);
+        assert_same_value("i = 0; for (; i < 5;) { i = i + 1; } i;", 5);
+        // 条件を省略したforは本体を実行しない
+        assert_same_value("x = 1; for (x = 2;;) { x = 3; } x;", 2);
+    }
+
+    #[test]
+    fn break_skips_update() {
+        assert_same_value("for (i=0; i<10; i=i+1) { if (i==3) { break; } } i;", 3);
+    }
+
+    #[test]
+    fn nested_for() {
+        assert_same_value(
+            "s = 0; for (i = 0; i < 3; i = i + 1) { for (j = 0; j < 4; j = j + 1) { s = s + 1; } } s;",
+            12,
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::{error::Error, fmt};
 
 use crate::{
+    eval::RuntimeError,
     lexer::LexicalError,
+    lint::Lint,
     parser::SyntaxError,
     token::{Span, Spanned},
 };
@@ -10,6 +12,9 @@ use crate::{
 pub enum CompilerError {
     Lexical(LexicalError),
     Syntax(SyntaxError),
+    Runtime(RuntimeError),
+    /// `--strict`でエラーとして扱う[`Lint`]
+    Lint(Lint),
 }
 
 impl Spanned for CompilerError {
@@ -17,6 +22,8 @@ impl Spanned for CompilerError {
         match self {
             Self::Lexical(e) => e.span(),
             Self::Syntax(e) => e.span(),
+            Self::Runtime(_) => None,
+            Self::Lint(e) => e.span(),
         }
     }
 }
@@ -28,6 +35,8 @@ impl fmt::Display for CompilerError {
         match self {
             CompilerError::Lexical(e) => write!(f, "Lexical error: {}", e),
             CompilerError::Syntax(e) => write!(f, "Syntax error: {}", e),
+            CompilerError::Runtime(e) => write!(f, "Runtime error: {}", e),
+            CompilerError::Lint(e) => write!(f, "Lint error: {}", e),
         }
     }
 }
@@ -44,11 +53,45 @@ impl From<SyntaxError> for CompilerError {
     }
 }
 
-/// エラーをソースコードとともに表示する
-pub fn format_error<E: Spanned + fmt::Display>(e: &E, source: &str) -> String {
-    if e.span().is_none() {
-        return format!("{}\n{}", e, source);
+impl From<Lint> for CompilerError {
+    fn from(e: Lint) -> Self {
+        CompilerError::Lint(e)
+    }
+}
+
+impl From<RuntimeError> for CompilerError {
+    fn from(e: RuntimeError) -> Self {
+        CompilerError::Runtime(e)
     }
+}
+
+/// エラー箇所の前後に表示する最大の文字数
+const CONTEXT_CHARS: usize = 40;
+/// 表示を省略した箇所に付ける記号
+const ELLIPSIS: &str = "...";
+
+/// `colored`の場合にエラー理由とキャレットに付ける色(赤の太字)
+const RED: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// エラーをソースコードとともに表示する
+///
+/// 長いソースの場合は、エラー箇所の前後[`CONTEXT_CHARS`]文字までを表示し、
+/// 省略した側に[`ELLIPSIS`]を付ける。
+/// 複数行のソースの場合は、エラー箇所を含む行のみを行番号付きで表示する。
+/// エラー箇所が改行をまたぐ場合は、またいだ全ての行を表示する。
+/// `colored`の場合は、エラー理由とキャレットをANSIエスケープシーケンスで赤く表示する。
+pub fn format_error<E: Spanned + fmt::Display>(e: &E, source: &str, colored: bool) -> String {
+    let (red, reset) = if colored { (RED, RESET) } else { ("", "") };
+    let message = format!("{}{}{}", red, e, reset);
+
+    let Some(span) = e.span() else {
+        // 空入力の場合はソース行を表示しない
+        if source.is_empty() {
+            return message;
+        }
+        return format!("{}\n{}", message, source);
+    };
 
     // 表示形式:
     // エラー理由
@@ -59,8 +102,159 @@ pub fn format_error<E: Spanned + fmt::Display>(e: &E, source: &str) -> String {
     // Syntax error: Unexpected token: Plus
     // 1 + +
     //     ^
-    let span = e.span().unwrap();
-    let space = " ".repeat(span.start);
-    let callet = "^".repeat(span.end - span.start);
-    format!("{}\n{}\n{}{}", e, source, space, callet)
+    if !source.contains('\n') {
+        let (line, callet) = snippet(source, span.start, span.end);
+        return format!("{}\n{}\n{}{}{}", message, line, red, callet, reset);
+    }
+
+    // 複数行の場合:
+    // Syntax error: Expected an expression, found Semicolon
+    // 2 | x + ;
+    //   |     ^
+    let mut offset = 0;
+    let mut shown = vec![];
+    for (i, line) in source.split('\n').enumerate() {
+        let (start, end) = (offset, offset + line.len());
+        offset = end + 1;
+
+        // 改行をまたぐ場合、各行にはその行に含まれる部分だけキャレットを付ける
+        let touches = start < span.end && span.start < end;
+        let starts_here = (start..=end).contains(&span.start);
+        if touches || starts_here {
+            let from = span.start.max(start) - start;
+            let to = span.end.clamp(start, end) - start;
+            shown.push((i + 1, snippet(line, from, to)));
+        }
+    }
+
+    let width = shown.last().map_or(1, |(n, _)| n.to_string().len());
+    let mut output = message;
+    for (number, (line, callet)) in shown {
+        output.push_str(&format!(
+            "\n{:>width$} | {}\n{:width$} | {}{}{}",
+            number, line, "", red, callet, reset
+        ));
+    }
+    output
+}
+
+/// `line`と、その`start..end`(`line`内のバイト位置)を指すキャレット行を返す。
+///
+/// キャレットは少なくとも1文字分表示する。
+fn snippet(line: &str, start: usize, end: usize) -> (String, String) {
+    let (before, rest) = line.split_at(start);
+    let (target, after) = rest.split_at(end.max(start) - start);
+
+    let skip = before.chars().count().saturating_sub(CONTEXT_CHARS);
+    let (head, before) = match before.char_indices().nth(skip) {
+        Some((i, _)) if skip > 0 => (ELLIPSIS, &before[i..]),
+        _ => ("", before),
+    };
+    let (after, tail) = match after.char_indices().nth(CONTEXT_CHARS) {
+        Some((i, _)) => (&after[..i], ELLIPSIS),
+        None => (after, ""),
+    };
+
+    let space = " ".repeat(head.len() + before.chars().count());
+    let callet = "^".repeat(target.chars().count().max(1));
+    (
+        format!("{}{}{}{}{}", head, before, target, after, tail),
+        format!("{}{}", space, callet),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{tok, token::TokenKind};
+
+    #[test]
+    fn short_source() {
+        let e = SyntaxError::UnexpectedToken(tok!(TokenKind::Plus, 4, 5));
+        assert_eq!(
+            format_error(&e, "1 + +", false),
+            "Unexpected token: Plus\n1 + +\n    ^"
+        );
+    }
+
+    #[test]
+    fn long_source_is_windowed() {
+        let source = format!("{}){}", "a".repeat(500), "b".repeat(500));
+        let e = SyntaxError::UnexpectedToken(tok!(TokenKind::RightParen, 500, 501));
+
+        let output = format_error(&e, &source, false);
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(
+            lines[1],
+            format!("...{}){}...", "a".repeat(40), "b".repeat(40))
+        );
+        assert_eq!(lines[2], format!("{}^", " ".repeat(43)));
+    }
+
+    #[test]
+    fn window_near_start_and_end() {
+        let source = format!("1 +{}", " ".repeat(100));
+        let e = SyntaxError::UnexpectedToken(tok!(TokenKind::Plus, 2, 3));
+
+        let output = format_error(&e, &source, false);
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines[1], format!("1 +{}...", " ".repeat(40)));
+        assert_eq!(lines[2], "  ^");
+    }
+
+    #[test]
+    fn colored_output() {
+        let e = SyntaxError::UnmatchedLeftParen(tok!(TokenKind::LeftParen, 0, 1));
+
+        let plain = format_error(&e, "(1 + 2", false);
+        assert!(!plain.contains('\x1b'));
+
+        let colored = format_error(&e, "(1 + 2", true);
+        assert!(colored.contains(RED) && colored.contains(RESET));
+        assert_eq!(colored.replace(RED, "").replace(RESET, ""), plain);
+    }
+
+    #[test]
+    fn multiline_source_shows_only_the_error_line() {
+        let source = "\
+x = 0;
+for (i = 0; i < 3; i = i + 1) {
+  if (i > 1) {
+    x = x + ;
+  }
+}";
+        let tokens = crate::lexer::Lexer::new(source).lex().unwrap();
+        let e = crate::parser::Parser::new(tokens).parse().unwrap_err();
+
+        assert_eq!(
+            format_error(&e, source, false),
+            "Expected an expression, found Semicolon\n4 |     x = x + ;\n  |             ^"
+        );
+    }
+
+    #[test]
+    fn two_char_operator_is_fully_underlined() {
+        let source = "x = 1 + >= 2;";
+        let tokens = crate::lexer::Lexer::new(source).lex().unwrap();
+        let e = crate::parser::Parser::new(tokens).parse().unwrap_err();
+
+        assert_eq!(
+            format_error(&e, source, false),
+            "Expected an expression, found GtEq\nx = 1 + >= 2;\n        ^^"
+        );
+    }
+
+    #[test]
+    fn span_across_lines() {
+        let source = "a = 1;\nb = (2 +\n3;\nc;";
+        // `(2 +\n3`をまたぐ
+        let e = SyntaxError::UnmatchedLeftParen(tok!(TokenKind::LeftParen, 11, 17));
+
+        assert_eq!(
+            format_error(&e, source, false),
+            "Unmatched left parenthesis\n2 | b = (2 +\n  |     ^^^^\n3 | 3;\n  | ^"
+        );
+    }
 }
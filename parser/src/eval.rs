@@ -0,0 +1,954 @@
+use std::{collections::HashMap, error::Error, fmt, str::FromStr};
+
+use crate::{
+    ast::{BinaryOp, Expression, For, If, Program, Statement, While},
+    error::CompilerError,
+    fold::const_value,
+    lexer::Lexer,
+    parser::Parser,
+    visit::variables,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum RuntimeError {
+    Overflow,
+    /// 0除算が起きた部分式を、[`Expression`]の`Display`形式で保持する
+    DivisionByZero(String),
+    NegativeExponent,
+    UndefinedVariable(String),
+    /// ループの反復回数が[`Evaluator::with_max_iterations`]で指定した上限を超えた
+    IterationLimit(usize),
+}
+
+impl Error for RuntimeError {}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow => write!(f, "Integer overflow"),
+            Self::DivisionByZero(expr) => write!(f, "Division by zero: {}", expr),
+            Self::NegativeExponent => write!(f, "Negative exponent"),
+            Self::UndefinedVariable(name) => write!(f, "Undefined variable: {}", name),
+            Self::IterationLimit(limit) => {
+                write!(f, "Loop iteration limit exceeded: {}", limit)
+            }
+        }
+    }
+}
+
+pub type EvalResult<T> = Result<T, RuntimeError>;
+
+/// 表示用の評価結果。
+///
+/// 値は全て`i32`で計算するため、真偽値かどうかは結果を返した式の形で決める。
+/// プログラムの最後の式文が比較演算の場合に`Bool`になる。
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value {
+    Int(i32),
+    Bool(bool),
+}
+
+impl Value {
+    /// `program`を評価した結果`v`を、最後の文の形に応じて分類する。
+    fn classify(program: &Program, v: i32) -> Self {
+        match program.body.last() {
+            Some(Statement::ExpressionStatement(Expression::Binary { op, .. }))
+                if op.is_comparison() =>
+            {
+                Value::Bool(v != 0)
+            }
+            _ => Value::Int(v),
+        }
+    }
+}
+
+/// 整数はそのまま、真偽値は`true`/`false`と表示する。
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+/// 算術演算の結果が`i32`に収まらない場合の振る舞い。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ArithMode {
+    /// Cと同様に2の補数で折り返す。
+    Wrapping,
+    /// オーバーフローを[`RuntimeError::Overflow`]として報告する。
+    #[default]
+    Checked,
+}
+
+impl FromStr for ArithMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrapping" => Ok(Self::Wrapping),
+            "checked" => Ok(Self::Checked),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ArithMode {
+    /// モードに応じて、checked演算またはwrapping演算の結果を選ぶ。
+    pub fn select(self, checked: Option<i32>, wrapping: i32) -> EvalResult<i32> {
+        match self {
+            ArithMode::Checked => checked.ok_or(RuntimeError::Overflow),
+            ArithMode::Wrapping => Ok(wrapping),
+        }
+    }
+}
+
+/// ソースコードを字句解析・構文解析し、評価した値を返す。
+///
+/// 各段階のエラーは[`CompilerError`]にまとめて返す。
+///
+/// ```rust
+/// use parser::eval::calc;
+///
+/// assert_eq!(calc("1+2*3;"), Ok(7));
+/// ```
+pub fn calc(input: &str) -> Result<i32, CompilerError> {
+    let tokens = Lexer::new(input).lex()?;
+    let program = Parser::new(tokens).parse()?;
+    Ok(Evaluator::new().eval(&program)?)
+}
+
+/// 変数の値を格納する場所。[`Evaluator`]はこのトレイトを通して変数を読み書きする。
+pub trait Env {
+    /// 変数に値を設定し、それまでの値を返す。未定義だった場合は`None`を返す。
+    ///
+    /// ブロックを抜ける際に、返された値で変数を元に戻すのに使える。
+    fn define(&mut self, name: &str, value: i32) -> Option<i32>;
+    fn get(&self, name: &str) -> Option<i32>;
+}
+
+/// 変数名と値の対応を`HashMap`で保持する。
+///
+/// 事前の準備が不要なため、入力ごとに変数が増えるREPLのような用途に向く。
+#[derive(Debug, Default, Clone)]
+pub struct Environment {
+    vars: HashMap<String, i32>,
+}
+
+impl Env for Environment {
+    fn define(&mut self, name: &str, value: i32) -> Option<i32> {
+        self.vars.insert(name.to_string(), value)
+    }
+
+    fn get(&self, name: &str) -> Option<i32> {
+        self.vars.get(name).copied()
+    }
+}
+
+/// 変数を密な`Vec`のスロットに割り当てて保持する。
+///
+/// [`SlotEnvironment::resolve`]でプログラム中の変数名を事前に集めてスロットを確保するため、
+/// 評価中はハッシュ計算も変数ごとのアロケーションも発生しない。
+/// 名前からスロットへの対応は、ソート済みの名前表に対する二分探索で求める。
+#[derive(Debug, Default, Clone)]
+pub struct SlotEnvironment {
+    /// ソート済みの変数名。`names[i]`の値は`slots[i]`に格納される
+    names: Vec<String>,
+    slots: Vec<Option<i32>>,
+}
+
+impl SlotEnvironment {
+    /// プログラム中に現れる全ての変数にスロットを割り当てる。
+    pub fn resolve(program: &Program) -> Self {
+        let names = variables(program);
+        let slots = vec![None; names.len()];
+        Self { names, slots }
+    }
+
+    fn slot(&self, name: &str) -> Result<usize, usize> {
+        self.names
+            .binary_search_by(|probe| probe.as_str().cmp(name))
+    }
+}
+
+impl Env for SlotEnvironment {
+    fn define(&mut self, name: &str, value: i32) -> Option<i32> {
+        match self.slot(name) {
+            Ok(i) => self.slots[i].replace(value),
+            // 解決済みのプログラムでは起こらないが、未知の変数もスロットを追加して扱う
+            Err(i) => {
+                self.names.insert(i, name.to_string());
+                self.slots.insert(i, Some(value));
+                None
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<i32> {
+        self.slot(name).ok().and_then(|i| self.slots[i])
+    }
+}
+
+/// 外部から与えられた変数の値の上に、評価中の代入を重ねる環境。
+///
+/// 代入は`outer`を書き換えず`local`に記録し、読み出しは`local`を優先する。
+struct Overlay<'a> {
+    outer: &'a HashMap<String, i32>,
+    local: Environment,
+}
+
+impl Env for Overlay<'_> {
+    /// `local`にまだ値がなければ、`outer`の値をそれまでの値として返す。
+    fn define(&mut self, name: &str, value: i32) -> Option<i32> {
+        self.local
+            .define(name, value)
+            .or_else(|| self.outer.get(name).copied())
+    }
+
+    fn get(&self, name: &str) -> Option<i32> {
+        self.local
+            .get(name)
+            .or_else(|| self.outer.get(name).copied())
+    }
+}
+
+/// [`Program`]を木構造のまま評価するインタプリタ。
+///
+/// ## 仕様
+///
+/// - 値は全て`i32`で、比較演算は`1`(真)または`0`(偽)を返す
+/// - `if`/`while`/`for`の条件は`0`より大きい場合に真とみなす(負の値は偽)。
+///   全てのバックエンドはこの規則に従う
+/// - `for`の条件を省略した場合は偽とみなし、本体を実行しない
+/// - `break`は最も内側のループを抜ける。`for`の更新式は実行しない
+/// - ブロック式の値は末尾の`;`のない式の値、そのような式がなければ`0`
+/// - 文の値は、式文はその式の値、ブロックと実行された`if`/`else`の本体は最後の文の値、それ以外は`0`
+/// - プログラムの値は最後の文の値。実行されなかった`if`や、一度も本体を実行しなかったループで
+///   終わるプログラムの値は`0`になる
+///
+/// 分岐やループの中も含めて、最後に実行した式文の値は[`Evaluator::last_value`]で参照できる。
+///
+/// 0除算は[`ArithMode`]によらず[`RuntimeError::DivisionByZero`]になる。
+///
+/// `Clone`で評価途中の状態を複製できる。複製した側での代入は元の`Evaluator`に影響しない。
+#[derive(Debug, Default, Clone)]
+pub struct Evaluator<E = Environment> {
+    env: E,
+    mode: ArithMode,
+    /// 全てのループを通した反復回数の上限。`None`の場合は無制限
+    max_iterations: Option<usize>,
+    iterations: usize,
+    /// `break`を実行してから、対応するループを抜けるまでの間`true`
+    breaking: bool,
+    /// 最後に実行した式文の値
+    last_value: Option<i32>,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mode(mode: ArithMode) -> Self {
+        Self::with_env(Environment::default(), mode)
+    }
+}
+
+impl<E: Env> Evaluator<E> {
+    pub fn with_env(env: E, mode: ArithMode) -> Self {
+        Self {
+            env,
+            mode,
+            max_iterations: None,
+            iterations: 0,
+            breaking: false,
+            last_value: None,
+        }
+    }
+
+    /// 変数の現在の値を参照する。
+    pub fn environment(&self) -> &E {
+        &self.env
+    }
+
+    /// 分岐やループの本体、ブロック式の中も含めて、最後に実行した式文の値を返す。
+    /// 式文を1つも実行していない場合は`None`を返す。
+    ///
+    /// [`Evaluator::eval`]の値と異なり、実行されなかった`if`や空文では変化しない。
+    /// 複数のプログラムを評価した場合は、それらを通して最後に実行した式文の値になる。
+    pub fn last_value(&self) -> Option<i32> {
+        self.last_value
+    }
+
+    /// ループの反復回数に上限を設ける。
+    ///
+    /// `while`/`for`の本体を実行した回数の合計が`limit`を超えると、
+    /// [`RuntimeError::IterationLimit`]を返す。無限ループを含むプログラムを安全に評価するために使う。
+    pub fn with_max_iterations(mut self, limit: usize) -> Self {
+        self.max_iterations = Some(limit);
+        self
+    }
+
+    pub fn eval(&mut self, program: &Program) -> EvalResult<i32> {
+        self.block(&program.body)
+    }
+
+    /// [`Evaluator::eval`]と同様に評価し、結果を表示用の[`Value`]として返す。
+    pub fn eval_value(&mut self, program: &Program) -> EvalResult<Value> {
+        let v = self.eval(program)?;
+        Ok(Value::classify(program, v))
+    }
+
+    /// プログラムをトップレベルの文ごとに1つずつ評価するイテレータを返す。
+    ///
+    /// `next()`のたびに文を1つ評価し、評価後の環境の複製を返す。
+    /// エラーが起きた場合はそのエラーを返し、以降は`None`を返す。
+    pub fn step_iter<'a>(&'a mut self, program: &'a Program) -> Steps<'a, E> {
+        Steps {
+            evaluator: self,
+            stmts: program.body.iter(),
+            failed: false,
+        }
+    }
+
+    fn block(&mut self, body: &[Statement]) -> EvalResult<i32> {
+        let mut value = 0;
+        for stmt in body {
+            value = self.stmt(stmt)?;
+            if self.breaking {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    fn stmt(&mut self, stmt: &Statement) -> EvalResult<i32> {
+        match stmt {
+            Statement::ExpressionStatement(expr) => {
+                let value = self.expr(expr)?;
+                self.last_value = Some(value);
+                Ok(value)
+            }
+            Statement::BlockStatement(body) => self.block(body),
+            Statement::If(If { cond, then, r#else }) => {
+                if is_truthy(self.expr(cond)?) {
+                    self.block(then)
+                } else if let Some(r#else) = r#else {
+                    self.block(r#else)
+                } else {
+                    Ok(0)
+                }
+            }
+            Statement::While(While { cond, body }) => {
+                let folded = const_value(cond);
+                while self.loop_cond(cond, folded)? {
+                    self.tick()?;
+                    self.block(body)?;
+                    if self.breaking {
+                        self.breaking = false;
+                        break;
+                    }
+                }
+                Ok(0)
+            }
+            Statement::For(For {
+                init,
+                cond,
+                update,
+                body,
+            }) => {
+                if let Some(init) = init {
+                    self.expr(init)?;
+                }
+                let folded = cond.as_ref().and_then(const_value);
+                while let Some(cond) = cond
+                    && self.loop_cond(cond, folded)?
+                {
+                    self.tick()?;
+                    self.block(body)?;
+                    // breakした場合は更新式を実行しない
+                    if self.breaking {
+                        self.breaking = false;
+                        break;
+                    }
+                    if let Some(update) = update {
+                        self.expr(update)?;
+                    }
+                }
+                Ok(0)
+            }
+            Statement::Break => {
+                self.breaking = true;
+                Ok(0)
+            }
+            Statement::Empty => Ok(0),
+        }
+    }
+
+    /// ループの条件を判定する。条件が定数の場合は、事前に畳み込んだ値`folded`を使い、式を評価し直さない。
+    fn loop_cond(&mut self, cond: &Expression, folded: Option<i32>) -> EvalResult<bool> {
+        let v = match folded {
+            Some(v) => v,
+            None => self.expr(cond)?,
+        };
+        Ok(is_truthy(v))
+    }
+
+    fn expr(&mut self, expr: &Expression) -> EvalResult<i32> {
+        match expr {
+            Expression::Value(n) => Ok(*n),
+            Expression::Var(name) => self
+                .env
+                .get(name)
+                .ok_or_else(|| RuntimeError::UndefinedVariable(name.clone())),
+            Expression::Unary { op, expr } => {
+                let v = self.expr(expr)?;
+                op.apply_with(self.mode, v)
+            }
+            Expression::Binary {
+                lhs,
+                op: BinaryOp::Assign,
+                rhs,
+                ..
+            } => {
+                // 代入先が変数であることはパーサーが保証している
+                let Expression::Var(name) = lhs.as_ref() else {
+                    unreachable!("assignment target must be a variable");
+                };
+                let v = self.expr(rhs)?;
+                self.env.define(name, v);
+                Ok(v)
+            }
+            Expression::Call { func, args } => {
+                // 引数は左から順に評価する
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<EvalResult<Vec<_>>>()?;
+                func.apply_with(self.mode, &args)
+            }
+            Expression::Block { body, value } => {
+                self.block(body)?;
+                match value {
+                    Some(value) => self.expr(value),
+                    // 末尾が`;`で終わるブロックの値は0
+                    None => Ok(0),
+                }
+            }
+            Expression::Binary { lhs, op, rhs, .. } => {
+                let l = self.expr(lhs)?;
+                let r = self.expr(rhs)?;
+                // BinaryOp::apply_withも0除算を検査するが、値しか分からないため、
+                // どの部分式で0除算が起きたか分かるよう、ここで式そのものを添えて報告する
+                if matches!(op, BinaryOp::Div | BinaryOp::Rem) && r == 0 {
+                    return Err(RuntimeError::DivisionByZero(expr.to_string()));
+                }
+                op.apply_with(self.mode, l, r)
+            }
+        }
+    }
+
+    /// ループの反復回数を数え、上限を超えた場合はエラーを返す。
+    fn tick(&mut self) -> EvalResult<()> {
+        self.iterations += 1;
+        match self.max_iterations {
+            Some(limit) if self.iterations > limit => Err(RuntimeError::IterationLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// [`Evaluator::step_iter`]が返すイテレータ。
+pub struct Steps<'a, E> {
+    evaluator: &'a mut Evaluator<E>,
+    stmts: std::slice::Iter<'a, Statement>,
+    failed: bool,
+}
+
+impl<E: Env + Clone> Iterator for Steps<'_, E> {
+    type Item = EvalResult<E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let stmt = self.stmts.next()?;
+        match self.evaluator.stmt(stmt) {
+            Ok(_) => Some(Ok(self.evaluator.env.clone())),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// 自由変数の値を`env`から与えて式を評価する。
+    ///
+    /// `env`にない変数は[`RuntimeError::UndefinedVariable`]になる。
+    /// 式中の代入は`env`には反映されない。
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    ///
+    /// use parser::{lexer::Lexer, parser::Parser};
+    ///
+    /// let tokens = Lexer::new("x * x").lex().unwrap();
+    /// let expr = Parser::new(tokens).parse_expr().unwrap();
+    /// let env = HashMap::from([("x".to_string(), 5)]);
+    /// assert_eq!(expr.eval_in(&env), Ok(25));
+    /// ```
+    pub fn eval_in(&self, env: &HashMap<String, i32>) -> EvalResult<i32> {
+        let env = Overlay {
+            outer: env,
+            local: Environment::default(),
+        };
+        Evaluator::with_env(env, ArithMode::default()).expr(self)
+    }
+}
+
+pub fn is_truthy(v: i32) -> bool {
+    v > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        parser::SyntaxError,
+        token::{Token, TokenKind},
+    };
+
+    fn eval_with(mode: ArithMode, input: &str) -> EvalResult<i32> {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Evaluator::with_mode(mode).eval(&program)
+    }
+
+    #[test]
+    fn calc_ok() {
+        assert_eq!(calc("1+2*3;"), Ok(7));
+    }
+
+    #[test]
+    fn calc_errors() {
+        assert_eq!(
+            calc("1/0;"),
+            Err(RuntimeError::DivisionByZero("(1 / 0)".to_string()).into())
+        );
+        assert!(matches!(calc("1 @ 2;"), Err(CompilerError::Lexical(_))));
+        assert!(matches!(calc("1 +;"), Err(CompilerError::Syntax(_))));
+    }
+
+    #[test]
+    fn slot_environment_matches_hash_map() {
+        const FIBONACCI: &str =
+            "n=10; a=0; b=1; for (i=0; i<n; i=i+1) { tmp = a; a = b; b = tmp + b;} a;";
+
+        let tokens = Lexer::new(FIBONACCI).lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let expected = Evaluator::new().eval(&program);
+        let env = SlotEnvironment::resolve(&program);
+        let actual = Evaluator::with_env(env, ArithMode::default()).eval(&program);
+
+        assert_eq!(expected, Ok(55));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn slot_environment_resolves_each_name_once() {
+        let tokens = Lexer::new("x = 1; if (x) { y = x; } else { x = y; }")
+            .lex()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let env = SlotEnvironment::resolve(&program);
+
+        assert_eq!(env.names, ["x", "y"]);
+        assert_eq!(env.get("x"), None);
+    }
+
+    #[test]
+    fn default_mode_is_checked() {
+        assert_eq!(ArithMode::default(), ArithMode::Checked);
+    }
+
+    #[test]
+    fn mul_overflow_checked() {
+        let result = eval_with(ArithMode::Checked, "100000 * 100000;");
+        assert_eq!(result, Err(RuntimeError::Overflow));
+    }
+
+    #[test]
+    fn mul_overflow_wrapping() {
+        let result = eval_with(ArithMode::Wrapping, "100000 * 100000;");
+        assert_eq!(result, Ok(100000i32.wrapping_mul(100000)));
+    }
+
+    #[test]
+    fn add_overflow() {
+        let input = "2147483647 + 1;";
+        assert_eq!(
+            eval_with(ArithMode::Checked, input),
+            Err(RuntimeError::Overflow)
+        );
+        assert_eq!(eval_with(ArithMode::Wrapping, input), Ok(i32::MIN));
+    }
+
+    #[test]
+    fn division_by_zero_in_both_modes() {
+        for mode in [ArithMode::Checked, ArithMode::Wrapping] {
+            assert_eq!(
+                eval_with(mode, "1 / 0;"),
+                Err(RuntimeError::DivisionByZero("(1 / 0)".to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn division_by_zero_reports_inner_expression() {
+        let result = eval_with(ArithMode::Checked, "1 + (2 / (3 - 3));");
+        assert_eq!(
+            result,
+            Err(RuntimeError::DivisionByZero("(2 / (3 - 3))".to_string()))
+        );
+    }
+
+    #[test]
+    fn remainder_by_zero() {
+        let result = eval_with(ArithMode::Checked, "7 % 3 + 1 % (2 - 2);");
+        assert_eq!(
+            result,
+            Err(RuntimeError::DivisionByZero("(1 % (2 - 2))".to_string()))
+        );
+    }
+
+    #[test]
+    fn double_negation() {
+        assert_eq!(eval_with(ArithMode::Checked, "-(-5);"), Ok(5));
+        assert_eq!(eval_with(ArithMode::Checked, "- -5;"), Ok(5));
+    }
+
+    #[test]
+    fn negation_overflow() {
+        let input = "-(-2147483647 - 1);";
+        assert_eq!(
+            eval_with(ArithMode::Checked, input),
+            Err(RuntimeError::Overflow)
+        );
+        assert_eq!(eval_with(ArithMode::Wrapping, input), Ok(i32::MIN));
+    }
+
+    #[test]
+    fn negative_exponent() {
+        let result = eval_with(ArithMode::Checked, "2 ^ -1;");
+        assert_eq!(result, Err(RuntimeError::NegativeExponent));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let result = eval_with(ArithMode::Checked, "x;");
+        assert_eq!(
+            result,
+            Err(RuntimeError::UndefinedVariable("x".to_string()))
+        );
+    }
+
+    fn expression(input: &str) -> Expression {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let mut program = Parser::new(tokens).parse().unwrap();
+        match program.body.pop() {
+            Some(Statement::ExpressionStatement(expr)) => expr,
+            stmt => panic!("expected an expression statement, got {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn eval_in_external_variables() {
+        let expr = expression("x * x;");
+        let env = HashMap::from([("x".to_string(), 5)]);
+        assert_eq!(expr.eval_in(&env), Ok(25));
+
+        // 同じ式を異なる値で繰り返し評価できる
+        let expr = expression("x ^ 2 + 1;");
+        let values: Vec<_> = (0..4)
+            .map(|x| expr.eval_in(&HashMap::from([("x".to_string(), x)])))
+            .collect();
+        assert_eq!(values, [Ok(1), Ok(2), Ok(5), Ok(10)]);
+    }
+
+    #[test]
+    fn eval_in_undefined_variable() {
+        let expr = expression("x + y;");
+        let env = HashMap::from([("x".to_string(), 1)]);
+        assert_eq!(
+            expr.eval_in(&env),
+            Err(RuntimeError::UndefinedVariable("y".to_string()))
+        );
+    }
+
+    #[test]
+    fn eval_in_does_not_modify_env() {
+        let expr = expression("x = x + 1;");
+        let env = HashMap::from([("x".to_string(), 1)]);
+        assert_eq!(expr.eval_in(&env), Ok(2));
+        assert_eq!(env["x"], 1);
+    }
+
+    fn eval_limited(limit: usize, input: &str) -> EvalResult<i32> {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Evaluator::new().with_max_iterations(limit).eval(&program)
+    }
+
+    #[test]
+    fn infinite_loop_hits_iteration_limit() {
+        assert_eq!(
+            eval_limited(1000, "while (1) {}"),
+            Err(RuntimeError::IterationLimit(1000))
+        );
+        assert_eq!(
+            eval_limited(1000, "for (i = 0; 1; i = i + 1) {}"),
+            Err(RuntimeError::IterationLimit(1000))
+        );
+    }
+
+    #[test]
+    fn iteration_limit_counts_all_loops() {
+        // ちょうど上限回の反復は許可する
+        assert_eq!(
+            eval_limited(10, "i = 0; while (i < 10) { i = i + 1; } i;"),
+            Ok(10)
+        );
+        // 2つのループの合計で上限を超える
+        assert_eq!(
+            eval_limited(
+                10,
+                "i = 0; while (i < 6) { i = i + 1; } for (j = 0; j < 6; j = j + 1) {}"
+            ),
+            Err(RuntimeError::IterationLimit(10))
+        );
+    }
+
+    #[test]
+    fn cloned_evaluator_is_independent() {
+        let parse = |input: &str| {
+            Parser::new(Lexer::new(input).lex().unwrap())
+                .parse()
+                .unwrap()
+        };
+
+        let mut original = Evaluator::new();
+        original.eval(&parse("x = 1;")).unwrap();
+
+        let mut fork = original.clone();
+        fork.eval(&parse("x = x + 1; y = 3;")).unwrap();
+
+        assert_eq!(fork.environment().get("x"), Some(2));
+        assert_eq!(fork.environment().get("y"), Some(3));
+        assert_eq!(original.environment().get("x"), Some(1));
+        assert_eq!(original.environment().get("y"), None);
+    }
+
+    #[test]
+    fn block_expression() {
+        assert_eq!(calc("y = { 1; 2; 3 }; y;"), Ok(3));
+        assert_eq!(calc("y = { x = 1; x + 1 }; y + x;"), Ok(3));
+        // 末尾が`;`で終わるブロックの値は0
+        assert_eq!(calc("y = { 1; 2; 3; }; y;"), Ok(0));
+        assert_eq!(calc("1 + { if (1) { 5; } };"), Ok(1));
+    }
+
+    /// 式の位置の`{}`は括弧と同じくグループ化に使える。文の位置の`{`はブロック文になる
+    #[test]
+    fn block_as_grouping() {
+        assert_eq!(calc("x = {1 + 2} * 3; x;"), Ok(9));
+        assert_eq!(calc("x = (1 + 2) * 3; x;"), Ok(9));
+        assert!(matches!(
+            calc("{1 + 2} * 3;"),
+            Err(CompilerError::Syntax(SyntaxError::UnexpectedToken(Token {
+                kind: TokenKind::RightBlock,
+                ..
+            })))
+        ));
+    }
+
+    #[test]
+    fn step_iter_yields_snapshots() {
+        let program = Parser::new(Lexer::new("x=1; x=2; x=3;").lex().unwrap())
+            .parse()
+            .unwrap();
+        let mut evaluator = Evaluator::new();
+
+        let xs: Vec<_> = evaluator
+            .step_iter(&program)
+            .map(|env| env.unwrap().get("x"))
+            .collect();
+        assert_eq!(xs, [Some(1), Some(2), Some(3)]);
+        assert_eq!(evaluator.environment().get("x"), Some(3));
+    }
+
+    #[test]
+    fn step_iter_stops_after_error() {
+        let program = Parser::new(Lexer::new("x=1; y; x=2;").lex().unwrap())
+            .parse()
+            .unwrap();
+        let mut evaluator = Evaluator::new();
+        let mut steps = evaluator.step_iter(&program);
+
+        assert_eq!(steps.next().unwrap().unwrap().get("x"), Some(1));
+        assert_eq!(
+            steps.next().unwrap().unwrap_err(),
+            RuntimeError::UndefinedVariable("y".to_string())
+        );
+        assert!(steps.next().is_none());
+    }
+
+    #[test]
+    fn constant_loop_condition() {
+        assert_eq!(
+            calc("for (i=0; 1; i=i+1) { if (i>=5) { break; } } i;"),
+            Ok(5)
+        );
+        assert_eq!(calc("x = 1; while (1 > 2) { x = 2; } x;"), Ok(1));
+        // 畳み込めない条件は毎回評価する
+        assert_eq!(
+            calc("x = 1; for (i = 0; 2147483647 + i; i = i + 1) { x = 2; }"),
+            Err(CompilerError::Runtime(RuntimeError::Overflow))
+        );
+    }
+
+    #[test]
+    fn break_skips_for_update() {
+        assert_eq!(
+            calc("for (i=0; i<10; i=i+1) { if (i==3) { break; } } i;"),
+            Ok(3)
+        );
+        assert_eq!(
+            calc("i = 0; while (1) { i = i + 1; if (i > 4) { break; } } i;"),
+            Ok(5)
+        );
+        // 内側のループだけを抜ける
+        assert_eq!(
+            calc(
+                "s = 0; for (i = 0; i < 3; i = i + 1) { for (j = 0; j < 3; j = j + 1) { if (j == 1) { break; } s = s + 1; } } s;"
+            ),
+            Ok(3)
+        );
+        // breakの後の文は実行しない
+        assert_eq!(calc("x = 0; while (1) { break; x = 1; } x;"), Ok(0));
+    }
+
+    #[test]
+    fn value_display() {
+        let eval_value = |input: &str| {
+            let program = Parser::new(Lexer::new(input).lex().unwrap())
+                .parse()
+                .unwrap();
+            Evaluator::new().eval_value(&program).unwrap()
+        };
+
+        assert_eq!(eval_value("1 < 2;"), Value::Bool(true));
+        assert_eq!(eval_value("1 < 2;").to_string(), "true");
+        assert_eq!(eval_value("x = 3; x == 4;").to_string(), "false");
+        assert_eq!(eval_value("3 + 4;").to_string(), "7");
+        // 比較の結果を使った計算は整数
+        assert_eq!(eval_value("(1 < 2) + 1;").to_string(), "2");
+        assert_eq!(eval_value("x = 1 < 2;").to_string(), "1");
+    }
+
+    #[test]
+    fn builtin_calls() {
+        assert_eq!(calc("abs(-5);"), Ok(5));
+        assert_eq!(calc("max(3, 7);"), Ok(7));
+        assert_eq!(calc("x = 4; min(x * 2, abs(-x - 5)) + max(-1, -2);"), Ok(7));
+        assert!(matches!(calc("min(1);"), Err(CompilerError::Syntax(_))));
+        assert_eq!(
+            calc("abs(-2147483647 - 1);"),
+            Err(CompilerError::Runtime(RuntimeError::Overflow))
+        );
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        assert_eq!(calc("x=0; ++x; x;"), Ok(1));
+        // 値は更新後の値
+        assert_eq!(calc("x=5; y = --x * 2; x + y;"), Ok(12));
+        assert_eq!(
+            calc("s = 0; for (i = 0; i < 4; ++i) { s = s + i; } s;"),
+            Ok(6)
+        );
+        assert_eq!(
+            calc("x = 2147483647; ++x;"),
+            Err(CompilerError::Runtime(RuntimeError::Overflow))
+        );
+    }
+
+    #[test]
+    fn define_returns_previous_value() {
+        let mut env = Environment::default();
+        assert_eq!(env.define("x", 1), None);
+        assert_eq!(env.define("x", 2), Some(1));
+        assert_eq!(env.get("x"), Some(2));
+
+        let program = Parser::new(Lexer::new("x = 1;").lex().unwrap())
+            .parse()
+            .unwrap();
+        let mut env = SlotEnvironment::resolve(&program);
+        assert_eq!(env.define("x", 1), None);
+        assert_eq!(env.define("x", 2), Some(1));
+        // 解決していない変数
+        assert_eq!(env.define("y", 3), None);
+        assert_eq!(env.define("y", 4), Some(3));
+
+        let outer = HashMap::from([("x".to_string(), 10)]);
+        let mut env = Overlay {
+            outer: &outer,
+            local: Environment::default(),
+        };
+        assert_eq!(env.define("x", 1), Some(10));
+        assert_eq!(env.define("x", 2), Some(1));
+        assert_eq!(env.define("y", 3), None);
+    }
+
+    #[test]
+    fn last_value() {
+        fn eval(input: &str) -> (i32, Option<i32>) {
+            let tokens = Lexer::new(input).lex().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut evaluator = Evaluator::new();
+            let value = evaluator.eval(&program).unwrap();
+            (value, evaluator.last_value())
+        }
+
+        #[rustfmt::skip]
+        const TESTS: &[(&str, i32, Option<i32>)] = &[
+            // 式文のみ
+            ("1 + 2;",                                           3, Some(3)),
+            ("x = 1; x + 4;",                                    5, Some(5)),
+            // 実行されなかったif
+            ("x = 5; if (x > 10) { x = 1; }",                    0, Some(5)),
+            ("x = 5; if (x > 10) { 1; } else if (x < 0) { 2; }", 0, Some(5)),
+            // 実行されたif
+            ("if (1) { 7; }",                                    7, Some(7)),
+            // 一度も本体を実行しなかったループ
+            ("x = 3; while (x < 0) { x = x - 1; }",              0, Some(3)),
+            ("for (i = 0; i < 0; i = i + 1) { 7; }",             0, None),
+            // 本体を実行したループ。条件式と更新式は式文ではない
+            ("i = 0; while (i < 3) { i = i + 1; }",              0, Some(3)),
+            ("for (i = 0; i < 10; i = i + 1) { if (i == 2) { break; } i * 10; }", 0, Some(10)),
+            // ブロック式の中の式文
+            ("y = { 4; 5 }; if (0) {}",                          0, Some(5)),
+            ("if (0) { 1; } { 2; 3; }",                          3, Some(3)),
+            // 空文では変化しない
+            ("1;;",                                              0, Some(1)),
+            (";",                                                0, None),
+        ];
+
+        for &(input, value, last) in TESTS {
+            assert_eq!(eval(input), (value, last), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::ast::{BinaryOp, Expression, For, If, Program, Statement, While};
+
+/// 定数式を畳み込み、値に置き換える。
+///
+/// 変数を含まない単項演算・二項演算を、[`BinaryOp::apply`]で計算した値に置き換える。
+/// オーバーフローや0除算になる式は、実行時にエラーを報告できるよう畳み込まずに残す。
+pub fn fold(program: Program) -> Program {
+    Program {
+        body: block(program.body),
+    }
+}
+
+/// 式が定数であればその値を返す。変数・代入・ブロック式を含む場合や、評価がエラーになる場合は`None`。
+pub fn const_value(expr: &Expression) -> Option<i32> {
+    match expr {
+        Expression::Value(n) => Some(*n),
+        Expression::Unary { op, expr } => op.apply(const_value(expr)?).ok(),
+        Expression::Binary {
+            op: BinaryOp::Assign,
+            ..
+        } => None,
+        Expression::Binary { lhs, op, rhs, .. } => {
+            op.apply(const_value(lhs)?, const_value(rhs)?).ok()
+        }
+        Expression::Call { func, args } => {
+            let args = args.iter().map(const_value).collect::<Option<Vec<_>>>()?;
+            func.apply(&args).ok()
+        }
+        Expression::Var(_) | Expression::Block { .. } => None,
+    }
+}
+
+fn block(body: Vec<Statement>) -> Vec<Statement> {
+    body.into_iter().map(stmt).collect()
+}
+
+fn stmt(stmt: Statement) -> Statement {
+    match stmt {
+        Statement::ExpressionStatement(e) => Statement::ExpressionStatement(expr(e)),
+        Statement::BlockStatement(body) => Statement::BlockStatement(block(body)),
+        Statement::If(If { cond, then, r#else }) => Statement::If(If {
+            cond: expr(cond),
+            then: block(then),
+            r#else: r#else.map(block),
+        }),
+        Statement::While(While { cond, body }) => Statement::While(While {
+            cond: expr(cond),
+            body: block(body),
+        }),
+        Statement::For(For {
+            init,
+            cond,
+            update,
+            body,
+        }) => Statement::For(For {
+            init: init.map(expr),
+            cond: cond.map(expr),
+            update: update.map(expr),
+            body: block(body),
+        }),
+        Statement::Break => Statement::Break,
+        Statement::Empty => Statement::Empty,
+    }
+}
+
+fn expr(expr: Expression) -> Expression {
+    let folded = match expr {
+        Expression::Unary { op, expr } => Expression::Unary {
+            op,
+            expr: Box::new(self::expr(*expr)),
+        },
+        Expression::Binary { lhs, op, rhs, span } => Expression::Binary {
+            lhs: Box::new(self::expr(*lhs)),
+            op,
+            rhs: Box::new(self::expr(*rhs)),
+            span,
+        },
+        Expression::Call { func, args } => Expression::Call {
+            func,
+            args: args.into_iter().map(self::expr).collect(),
+        },
+        Expression::Block { body, value } => Expression::Block {
+            body: block(body),
+            value: value.map(|e| Box::new(self::expr(*e))),
+        },
+        Expression::Value(_) | Expression::Var(_) => expr,
+    };
+    // 部分式は畳み込み済みのため、値同士の演算かどうかだけを見ればよい
+    match const_value(&folded) {
+        Some(n) => Expression::Value(n),
+        None => folded,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn parse(input: &str) -> Program {
+        let tokens = Lexer::new(input).lex().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    fn fold_to_string(input: &str) -> String {
+        fold(parse(input))
+            .body
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(fold_to_string("1 + 2 * 3;"), "7;");
+        assert_eq!(fold_to_string("-(2 ^ 3) < 0;"), "1;");
+        assert_eq!(fold_to_string("x = 2 * 3 + x;"), "(x = (6 + x));");
+        assert_eq!(
+            fold_to_string("while (1 > 2) { y = { 1 + 1 }; }"),
+            "while (0) { (y = { 2 }); }"
+        );
+    }
+
+    #[test]
+    fn keeps_expressions_that_fail() {
+        assert_eq!(fold_to_string("1 / 0;"), "(1 / 0);");
+        assert_eq!(fold_to_string("2147483647 + 1;"), "(2147483647 + 1);");
+        assert_eq!(fold_to_string("2 ^ -1;"), "(2 ^ -1);");
+    }
+
+    #[test]
+    fn const_value_of_expressions() {
+        let value = |input: &str| match &parse(input).body[0] {
+            Statement::ExpressionStatement(e) => const_value(e),
+            stmt => panic!("expected an expression statement, got {:?}", stmt),
+        };
+        assert_eq!(value("1 + 2;"), Some(3));
+        assert_eq!(value("x + 2;"), None);
+        assert_eq!(value("x = 2;"), None);
+        assert_eq!(value("1 + { 2 };"), None);
+    }
+}
@@ -6,7 +6,22 @@ pub type LexResult<T> = Result<T, LexicalError>;
 
 #[derive(Debug, PartialEq)]
 pub enum LexicalError {
-    InvalidToken(String, Span),
+    InvalidToken {
+        token: String,
+        span: Span,
+        /// 意図していたと思われるトークン
+        suggestion: Option<String>,
+    },
+    NumberOutOfRange(Span),
+    /// `0`で始まる2桁以上の数値リテラル。将来の基数プレフィックス(`0x`など)のために予約している
+    LeadingZero(Span),
+    /// `10i64`のような型サフィックス付きの数値リテラル。数値は全て`i32`のためサフィックスは受け付けない
+    NumberSuffix(String, Span),
+    /// トークン数が[`Lexer::with_max_tokens`]で指定した上限を超えた。上限を超えたトークンの範囲を保持する
+    TooLarge {
+        limit: usize,
+        span: Span,
+    },
     Eof, // センチネルエラー
 }
 
@@ -17,7 +32,22 @@ impl fmt::Display for LexicalError {
         use crate::lexer::LexicalError::*;
 
         match self {
-            InvalidToken(s, _) => write!(f, "Invalid token: {}", s),
+            InvalidToken {
+                token,
+                suggestion: Some(suggestion),
+                ..
+            } => write!(
+                f,
+                "Invalid token: {} (did you mean `{}`?)",
+                token, suggestion
+            ),
+            InvalidToken { token, .. } => write!(f, "Invalid token: {}", token),
+            NumberOutOfRange(_) => write!(f, "Number out of range"),
+            LeadingZero(_) => write!(f, "Leading zeros in number literal are not allowed"),
+            NumberSuffix(suffix, _) => {
+                write!(f, "Number literal suffix is not supported: {}", suffix)
+            }
+            TooLarge { limit, .. } => write!(f, "Too many tokens: the limit is {}", limit),
             Eof => write!(f, "End of File"),
         }
     }
@@ -26,41 +56,158 @@ impl fmt::Display for LexicalError {
 impl Spanned for LexicalError {
     fn span(&self) -> Option<Span> {
         match self {
-            Self::InvalidToken(_, span) => Some(span.clone()),
+            Self::InvalidToken { span, .. }
+            | Self::NumberOutOfRange(span)
+            | Self::LeadingZero(span)
+            | Self::NumberSuffix(_, span)
+            | Self::TooLarge { span, .. } => Some(span.clone()),
             _ => None,
         }
     }
 }
 
+/// `c`からトークンを読み始められるか判定する。
+fn starts_token(c: char) -> bool {
+    c.is_ascii_digit() || c.is_alphabetic() || "+-*/%^();,{}=!<>".contains(c)
+}
+
+/// トークナイズできない文字に対して、意図していたと思われるトークンを返す。
+///
+/// 全角記号や数学記号など、見た目の似た文字を対応するトークンに読み替える。
+fn suggest(c: char) -> Option<&'static str> {
+    let suggestion = match c {
+        '＋' => "+",
+        '－' | '−' => "-",
+        '＊' | '×' => "*",
+        '／' | '÷' => "/",
+        '％' => "%",
+        '＾' => "^",
+        '（' => "(",
+        '）' => ")",
+        '；' => ";",
+        '｛' => "{",
+        '｝' => "}",
+        '＝' => "=",
+        '！' => "!",
+        '＜' => "<",
+        '＞' => ">",
+        '≠' => "!=",
+        '≤' | '≦' => "<=",
+        '≥' | '≧' => ">=",
+        _ => return None,
+    };
+    Some(suggestion)
+}
+
+/// 数値リテラルの直後に続いた場合に、型サフィックスとみなしてエラーにする識別子。
+/// これ以外の識別子(`10x`の`x`など)は、数値とは別のトークンとして読む。
+const TYPE_SUFFIXES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+
+/// 既に`count`個のトークンを読んだ後に、`tok`を追加できるか判定する。
+/// `max_tokens`は[`Lexer::with_max_tokens`]で指定した上限。
+fn check_size(max_tokens: Option<usize>, count: usize, tok: &Token) -> LexResult<()> {
+    match max_tokens {
+        Some(limit) if count >= limit => Err(LexicalError::TooLarge {
+            limit,
+            span: tok.span.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// `i32::MAX`(2147483647)の桁数。
+/// これより長い数値リテラルは、末尾まで読まずにエラーにする。
+const MAX_NUMBER_DIGITS: usize = 10;
+
 pub struct Lexer<'a> {
     pos: usize,
     input: &'a str,
+    /// `true`の場合、改行を読み飛ばさずに`Newline`トークンとして出力する
+    newlines: bool,
+    /// [`Lexer::lex`]などで読むトークン数の上限。`None`の場合は無制限
+    max_tokens: Option<usize>,
+    /// 直前に桁数の上限で打ち切った数値の、残りの数字を次のトークンを読む前に読み飛ばす
+    truncated_number: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { pos: 0, input }
+        Lexer {
+            pos: 0,
+            input,
+            newlines: false,
+            max_tokens: None,
+            truncated_number: false,
+        }
+    }
+
+    /// 改行を`Newline`トークンとして出力するレキサーを作る。
+    /// [`Parser::with_newlines`](crate::parser::Parser::with_newlines)と組み合わせて使う。
+    pub fn with_newlines(input: &'a str) -> Self {
+        Lexer {
+            newlines: true,
+            ..Lexer::new(input)
+        }
+    }
+
+    /// 入力全体を読む場合のトークン数に上限を設ける。
+    ///
+    /// [`Lexer::lex`]と[`Lexer::lex_recover`]は、`limit`個を超えるトークンを読んだ時点で
+    /// [`LexicalError::TooLarge`]を返し、残りの入力を読まない。巨大な入力に対して、
+    /// トークン列を確保し続けないために使う。
+    pub fn with_max_tokens(mut self, limit: usize) -> Self {
+        self.max_tokens = Some(limit);
+        self
     }
 
     /// 入力全体をトークナイズし、トークン列を返す。
     /// 文字列をトークン化できない場合、エラーを返す。
     ///
-    /// - 空白は読み飛ばす
+    /// - 空白とコメント(`//`から行末まで)は読み飛ばす
+    /// - 入力の先頭行が`#!`で始まる場合、シバンとして行末まで読み飛ばす
+    /// - `@if`のように`@`に続く識別子は、キーワードと同じ名前でも`Ident`とする
     /// - 返却するトークン列に`Eof`は含めない
+    ///
+    /// 全てのトークンを読み終えるまでトークン列を保持する。
+    /// 1つずつ処理する場合は、[`Iterator`]として使う。
     pub fn lex(&mut self) -> LexResult<Vec<Token>> {
+        let max_tokens = self.max_tokens;
         let mut tokens = Vec::new();
-        loop {
-            let tok = self.next_token();
-            match tok {
-                Ok(t) => tokens.push(t),
-                Err(LexicalError::Eof) => break,
-                Err(e) => return Err(e),
-            };
+        for tok in self.by_ref() {
+            let tok = tok?;
+            check_size(max_tokens, tokens.len(), &tok)?;
+            tokens.push(tok);
         }
 
         Ok(tokens)
     }
 
+    /// エラーがあっても字句解析を続け、トークン列と見つかった全てのエラーを返す。
+    ///
+    /// 不正な文字はエラーとして記録して読み飛ばし、続きから再開する。
+    /// 返却するトークン列にはエラーとならなかったトークンのみが含まれる。
+    pub fn lex_recover(&mut self) -> (Vec<Token>, Vec<LexicalError>) {
+        let max_tokens = self.max_tokens;
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for tok in self.by_ref() {
+            match tok {
+                Ok(t) => {
+                    if let Err(e) = check_size(max_tokens, tokens.len(), &t) {
+                        errors.push(e);
+                        break;
+                    }
+                    tokens.push(t);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (tokens, errors)
+    }
+
     /// 現在位置から1トークン読み進め、トークンを返す。
     /// EoFに到達した場合は、`LexicalError::Eof`を返す。
     /// トークナイズできない場合、`LexicalError::InvalidToken`を返す。
@@ -76,14 +223,30 @@ impl<'a> Lexer<'a> {
         };
 
         let kind = match char {
-            '+' => Plus,
-            '-' => Minus,
+            '+' => {
+                if self.eat('+') {
+                    Increment
+                } else {
+                    Plus
+                }
+            }
+            '-' => {
+                if self.eat('-') {
+                    Decrement
+                } else {
+                    Minus
+                }
+            }
             '*' => Mul,
             '/' => Div,
+            '%' => Percent,
             '^' => Pow,
             '(' => LeftParen,
             ')' => RightParen,
             ';' => Semicolon,
+            ',' => Comma,
+            // 改行モードでなければskip_whitespaceで読み飛ばされるため、ここには来ない
+            '\n' => Newline,
             '{' => LeftBlock,
             '}' => RightBlock,
 
@@ -94,7 +257,13 @@ impl<'a> Lexer<'a> {
                     Assign
                 }
             }
-            '!' if self.eat('=') => Neq,
+            '!' => {
+                if self.eat('=') {
+                    Neq
+                } else {
+                    Bang
+                }
+            }
             '<' => {
                 if self.eat('=') {
                     LtEq
@@ -111,26 +280,43 @@ impl<'a> Lexer<'a> {
             }
 
             c if c.is_ascii_digit() => {
-                let num = self.next_number();
+                let num = self.next_number(start)?;
                 Num(num)
             }
+            // 生識別子。`@`を除いた名前を、キーワードの表を引かずに識別子とする
+            '@' if self.peek().is_some_and(char::is_alphabetic) => {
+                let ident = self.next_ident(self.pos);
+                Ident(ident.to_string())
+            }
             c if c.is_alphabetic() => {
-                let ident = self.next_ident();
+                let ident = self.next_ident(start);
                 match ident {
                     "if" => If,
+                    "else" => Else,
                     "while" => While,
                     "for" => For,
+                    "break" => Break,
                     _ => Ident(ident.to_string()),
                 }
             }
             c => {
-                return Err(LexicalError::InvalidToken(
-                    c.to_string(),
-                    Span {
+                // `@#$`のように不正な文字が続く場合は、まとめて1つのエラーにする
+                self.bump_while(|c| !starts_token(c) && !c.is_whitespace());
+                let token = &self.input[start..self.pos];
+                // 候補は1文字だけの場合に示す
+                let suggestion = if token.len() == c.len_utf8() {
+                    suggest(c).map(str::to_string)
+                } else {
+                    None
+                };
+                return Err(LexicalError::InvalidToken {
+                    token: token.to_string(),
+                    span: Span {
                         start,
-                        end: start + c.len_utf8(),
+                        end: self.pos,
                     },
-                ));
+                    suggestion,
+                });
             }
         };
         let end = self.pos;
@@ -141,12 +327,52 @@ impl<'a> Lexer<'a> {
         })
     }
 
+    /// 空白と`//`から行末までのコメントを読み飛ばす。
+    /// 入力の先頭では、`#!`から行末までのシバンも読み飛ばす。
     fn skip_whitespace(&mut self) {
-        while let Some(c) = self.peek() {
-            if !c.is_whitespace() {
+        if self.pos == 0 && self.input.starts_with("#!") {
+            self.skip_line();
+        }
+
+        let newlines = self.newlines;
+        loop {
+            self.bump_while(|c| c.is_whitespace() && !(newlines && c == '\n'));
+
+            if !self.input[self.pos..].starts_with("//") {
                 break;
             }
-            self.bump();
+            self.skip_line();
+        }
+    }
+
+    /// 行末まで読み飛ばす。
+    ///
+    /// 改行も含めて読み飛ばす。改行がなければ入力の末尾まで読み飛ばす。
+    /// 改行モードでは改行をトークンとして残す。
+    fn skip_line(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest
+            .find('\n')
+            .map_or(rest.len(), |i| if self.newlines { i } else { i + 1 });
+    }
+
+    /// `pred`を満たす文字が続く限り、ポインタを進める。
+    ///
+    /// ASCII文字はバイト列のまま判定し、マルチバイト文字の場合のみ`char`にデコードする。
+    fn bump_while(&mut self, pred: impl Fn(char) -> bool) {
+        let bytes = self.input.as_bytes();
+        while let Some(&b) = bytes.get(self.pos) {
+            if b.is_ascii() {
+                if !pred(b as char) {
+                    break;
+                }
+                self.pos += 1;
+            } else {
+                match self.peek() {
+                    Some(c) if pred(c) => self.pos += c.len_utf8(),
+                    _ => break,
+                }
+            }
         }
     }
 
@@ -178,37 +404,106 @@ impl<'a> Lexer<'a> {
         true
     }
 
-    pub fn next_number(&mut self) -> i32 {
-        // この関数に渡ってくる段階ですでに１文字目が読まれている
-        let start = self.pos - 1;
-        while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
-                self.bump();
-            } else {
-                break;
-            }
+    /// `start`から始まる数値リテラルを読み進め、`i32`として返す。
+    /// `i32`に収まらない場合は`LexicalError::NumberOutOfRange`を返す。
+    ///
+    /// `0`に続く数字は`LexicalError::LeadingZero`とする。
+    /// `007`を10進数とみなすか8進数とみなすかは言語によって異なり、
+    /// `0x`などの基数プレフィックスを導入する余地を残すため受け付けない。
+    ///
+    /// 直後に[`TYPE_SUFFIXES`]のいずれかが続く場合は`LexicalError::NumberSuffix`とする。
+    pub fn next_number(&mut self, start: usize) -> LexResult<i32> {
+        let bytes = self.input.as_bytes();
+        let leading_zero =
+            bytes[start] == b'0' && bytes.get(self.pos).is_some_and(u8::is_ascii_digit);
+        let truncated = self.bump_digits(start);
+        if leading_zero {
+            return Err(LexicalError::LeadingZero(Span {
+                start,
+                end: self.pos,
+            }));
+        }
+        if truncated {
+            return Err(LexicalError::NumberOutOfRange(Span {
+                start,
+                end: self.pos,
+            }));
+        }
+
+        let rest = &self.input[self.pos..];
+        let suffix_len = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let suffix = &rest[..suffix_len];
+        if TYPE_SUFFIXES.contains(&suffix) {
+            self.pos += suffix_len;
+            return Err(LexicalError::NumberSuffix(
+                suffix.to_string(),
+                Span {
+                    start,
+                    end: self.pos,
+                },
+            ));
         }
 
         let num_str = &self.input[start..self.pos];
-        // Safety: ascii_digitの文字列で構成されているため、安全にパースできる
-        num_str.parse::<i32>().unwrap()
+        // ascii_digitのみで構成されているため、失敗するのは`i32`の範囲外の場合のみ
+        num_str.parse::<i32>().map_err(|_| {
+            LexicalError::NumberOutOfRange(Span {
+                start,
+                end: self.pos,
+            })
+        })
     }
 
-    pub fn next_ident(&mut self) -> &str {
-        // この関数に渡ってくる段階ですでに１文字目が読まれている
-        let start = self.pos - 1;
-        while let Some(c) = self.peek() {
-            if c.is_alphanumeric() {
-                self.bump();
-            } else {
-                break;
+    /// `start`から始まる数字の並びを読み進める。
+    ///
+    /// 巨大なリテラルを最後まで走査しないよう、桁数が[`MAX_NUMBER_DIGITS`]を超えた時点で打ち切り、`true`を返す。
+    /// 残りの数字は、続きを読む場合に[`Iterator::next`]が読み飛ばす。
+    fn bump_digits(&mut self, start: usize) -> bool {
+        let bytes = self.input.as_bytes();
+        while let Some(b) = bytes.get(self.pos)
+            && b.is_ascii_digit()
+        {
+            self.pos += 1;
+            if self.pos - start > MAX_NUMBER_DIGITS {
+                self.truncated_number = true;
+                return true;
             }
         }
+        false
+    }
+
+    /// `start`から始まる識別子を読み進め、その文字列を返す。
+    pub fn next_ident(&mut self, start: usize) -> &str {
+        // 1文字目はマルチバイト文字の場合があるため、`self.pos - 1`ではなく`start`から切り出す
+        self.bump_while(char::is_alphanumeric);
 
         &self.input[start..self.pos]
     }
 }
 
+/// トークンを1つずつ読む。[`Lexer::next_token`]と異なり、入力の終端では`None`を返す。
+///
+/// エラーの後も続けて読むと、エラーとなった部分の後から再開する。
+/// [`Lexer::with_max_tokens`]の上限は適用しない。
+impl Iterator for Lexer<'_> {
+    type Item = LexResult<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // 桁数の上限で打ち切った数値は、残りの数字も同じ数値の一部として読み飛ばす。
+        // エラーを返す時点で読み飛ばすと、最初のエラーで止まる`Lexer::lex`でも巨大な数値を末尾まで読んでしまうため、
+        // 続きを読む場合にのみ行う
+        if std::mem::take(&mut self.truncated_number) {
+            self.bump_while(|c| c.is_ascii_digit());
+        }
+        match self.next_token() {
+            Err(LexicalError::Eof) => None,
+            tok => Some(tok),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::fmt::Write;
@@ -217,36 +512,268 @@ mod test {
 
     fn format_lexer_test(name: &str, source: &str) -> String {
         let mut lexer = Lexer::new(source);
-        let tokens = lexer.lex().unwrap();
 
         let mut output = format!("=== {} ===\nsource: {}\n\n", name, source);
-        for token in tokens {
-            writeln!(
-                output,
-                "[{}..{}]\t{:?}",
-                token.span.start, token.span.end, token.kind
-            )
-            .unwrap();
+        match lexer.lex() {
+            Ok(tokens) => {
+                for token in tokens {
+                    writeln!(
+                        output,
+                        "[{}..{}]\t{:?}",
+                        token.span.start, token.span.end, token.kind
+                    )
+                    .unwrap();
+                }
+            }
+            Err(e) => writeln!(output, "error: {:?}", e).unwrap(),
         }
         output.push('\n');
         output
     }
 
+    #[test]
+    fn max_number() {
+        let tokens = Lexer::new("2147483647").lex().unwrap();
+        assert_eq!(tokens[0].kind, crate::token::TokenKind::Num(i32::MAX));
+    }
+
+    #[test]
+    fn number_out_of_range() {
+        let result = Lexer::new("2147483648").lex();
+        assert_eq!(
+            result,
+            Err(LexicalError::NumberOutOfRange(Span { start: 0, end: 10 }))
+        );
+    }
+
+    #[test]
+    fn exponent_is_not_part_of_number() {
+        use crate::token::TokenKind::*;
+
+        // 数値は`i32`のみで指数表記は未対応のため、`e`以降は識別子として読む
+        let kinds: Vec<_> = Lexer::new("1e3")
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(kinds, [Num(1), Ident("e3".to_string())]);
+    }
+
+    #[test]
+    fn huge_number_stops_early() {
+        let source = "1".repeat(1000);
+        let result = Lexer::new(&source).lex();
+
+        // 上限の桁数を1つ超えた時点で打ち切る
+        assert_eq!(
+            result,
+            Err(LexicalError::NumberOutOfRange(Span {
+                start: 0,
+                end: MAX_NUMBER_DIGITS + 1
+            }))
+        );
+    }
+
+    #[test]
+    fn lex_stops_at_huge_number() {
+        let source = "1".repeat(1_000_000);
+        let mut lexer = Lexer::new(&source);
+
+        assert!(matches!(
+            lexer.lex(),
+            Err(LexicalError::NumberOutOfRange(_))
+        ));
+        // 残りの数字は読まない
+        assert_eq!(lexer.pos, MAX_NUMBER_DIGITS + 1);
+
+        // `0`で始まる場合も同じ位置で打ち切る
+        let source = format!("0{}", "1".repeat(1_000_000));
+        let mut lexer = Lexer::new(&source);
+        assert_eq!(
+            lexer.lex(),
+            Err(LexicalError::LeadingZero(Span {
+                start: 0,
+                end: MAX_NUMBER_DIGITS + 1
+            }))
+        );
+        assert_eq!(lexer.pos, MAX_NUMBER_DIGITS + 1);
+
+        // 続きを読む場合は、残りの数字を同じ数値の一部として読み飛ばす
+        let source = format!("0{} + 1", "1".repeat(100));
+        let (tokens, errors) = Lexer::new(&source).lex_recover();
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(errors[..], [LexicalError::LeadingZero(_)]));
+    }
+
+    #[test]
+    fn invalid_run_is_one_token() {
+        let result = Lexer::new("1 @#$ 2").lex();
+        assert_eq!(
+            result,
+            Err(LexicalError::InvalidToken {
+                token: "@#$".to_string(),
+                span: Span { start: 2, end: 5 },
+                suggestion: None,
+            })
+        );
+
+        // 次のトークンの直前で止まる
+        let mut lexer = Lexer::new("@#x");
+        assert!(matches!(
+            lexer.next_token(),
+            Err(LexicalError::InvalidToken {
+                span: Span { start: 0, end: 2 },
+                ..
+            })
+        ));
+        assert_eq!(
+            lexer.next_token().map(|t| t.kind),
+            Ok(crate::token::TokenKind::Ident("x".to_string()))
+        );
+
+        let mut lexer = Lexer::new("@,");
+        assert!(matches!(
+            lexer.next_token(),
+            Err(LexicalError::InvalidToken {
+                span: Span { start: 0, end: 1 },
+                ..
+            })
+        ));
+        assert_eq!(
+            lexer.next_token().map(|t| t.kind),
+            Ok(crate::token::TokenKind::Comma)
+        );
+    }
+
+    #[test]
+    fn invalid_token_suggestion() {
+        let result = Lexer::new("x ≥ 1").lex();
+        assert_eq!(
+            result,
+            Err(LexicalError::InvalidToken {
+                token: "≥".to_string(),
+                span: Span { start: 2, end: 5 },
+                suggestion: Some(">=".to_string()),
+            })
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Invalid token: ≥ (did you mean `>=`?)"
+        );
+    }
+
+    #[test]
+    fn invalid_token_without_suggestion() {
+        // `&`や`|`に対応する演算子はないため、候補を示さない
+        for source in ["1 @ 2", "1 & 2", "1 | 2"] {
+            let Err(LexicalError::InvalidToken { suggestion, .. }) = Lexer::new(source).lex()
+            else {
+                panic!("expected an invalid token: {}", source);
+            };
+            assert_eq!(suggestion, None, "{}", source);
+        }
+    }
+
+    #[test]
+    fn ampersand_has_no_suggestion() {
+        // 論理演算子はまだないため、`&`に`&&`を候補として示さない
+        let result = Lexer::new("x & y").lex();
+        assert_eq!(
+            result,
+            Err(LexicalError::InvalidToken {
+                token: "&".to_string(),
+                span: Span { start: 2, end: 3 },
+                suggestion: None,
+            })
+        );
+        assert_eq!(result.unwrap_err().to_string(), "Invalid token: &");
+
+        assert_eq!(
+            Lexer::new("x && y").lex(),
+            Err(LexicalError::InvalidToken {
+                token: "&&".to_string(),
+                span: Span { start: 2, end: 4 },
+                suggestion: None,
+            })
+        );
+    }
+
+    #[test]
+    fn token_kind_frequencies() {
+        use std::collections::HashMap;
+
+        use crate::token::TokenKind::{self, *};
+
+        let mut counts: HashMap<TokenKind, usize> = HashMap::new();
+        for token in Lexer::new("x = 1; y = x + 1; x + y;").lex().unwrap() {
+            *counts.entry(token.kind).or_default() += 1;
+        }
+
+        assert_eq!(counts[&Ident("x".to_string())], 3);
+        assert_eq!(counts[&Ident("y".to_string())], 2);
+        assert_eq!(counts[&Num(1)], 2);
+        assert_eq!(counts[&Assign], 2);
+        assert_eq!(counts[&Plus], 2);
+        assert_eq!(counts[&Semicolon], 3);
+        assert_eq!(counts.values().sum::<usize>(), 14);
+    }
+
+    #[test]
+    fn multibyte_spans() {
+        use crate::token::TokenKind::*;
+
+        // 全角スペース(3バイト)と垂直タブ、マルチバイトの識別子を含む
+        let source = "変数\u{3000}=\u{0B}12;\u{3000}x変2 >= 345";
+        let kinds_and_spans: Vec<_> = Lexer::new(source)
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.span.start, t.span.end))
+            .collect();
+
+        assert_eq!(
+            kinds_and_spans,
+            [
+                (Ident("変数".to_string()), 0, 6),
+                (Assign, 9, 10),
+                (Num(12), 11, 13),
+                (Semicolon, 13, 14),
+                (Ident("x変2".to_string()), 17, 22),
+                (GtEq, 23, 25),
+                (Num(345), 26, 29),
+            ]
+        );
+        for (_, start, end) in &kinds_and_spans {
+            assert!(source.is_char_boundary(*start) && source.is_char_boundary(*end));
+        }
+    }
+
     #[test]
     fn lexer() {
         #[rustfmt::skip]
         const TESTS: &[(&str, &str)] = &[
             ("plus_operator",        "+"),
             ("number_literal",       "123"),
+            ("zero",                 "0 10 100"),
+            ("leading_zero",         "007"),
+            ("leading_zero_eight",   "08"),
+            ("number_suffix",        "10i64"),
+            ("number_then_ident",    "10x"),
             ("plus_and_number",      "+ 123"),
             ("parenthesized_expr",   "(1)"),
             ("power_operator",       "^"),
             ("comparison_operators", "== != < <= > >="),
+            ("bang",                 "!= ! ! ="),
             ("assignment_statement", "x=1; x"),
             ("if_keyword",           "if"),
             ("if_statement",         "if (1>=0) {x=2;}"),
+            ("if_else_statement",    "if (x) {} else {}"),
             ("while_loop",           "while(){}"),
             ("for_loop",             "for(i=0;i<1;i=i+1) {}"),
+            ("line_comment",         "1 + 2 // ignored"),
+            ("comment_only",         "// all comment"),
+            ("multiline_comment",    "x = 1; // first\nx / 2;"),
         ];
 
         let output = TESTS
@@ -256,4 +783,318 @@ mod test {
 
         insta::assert_snapshot!(output);
     }
+
+    #[test]
+    fn newline_tokens() {
+        use crate::token::TokenKind::*;
+
+        // コメントは改行の手前まで読み飛ばす
+        let kinds_and_spans: Vec<_> = Lexer::with_newlines("x = 1 // c\n\ny")
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.span.start, t.span.end))
+            .collect();
+
+        assert_eq!(
+            kinds_and_spans,
+            [
+                (Ident("x".to_string()), 0, 1),
+                (Assign, 2, 3),
+                (Num(1), 4, 5),
+                (Newline, 10, 11),
+                (Newline, 11, 12),
+                (Ident("y".to_string()), 12, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn shebang_line_is_skipped() {
+        use crate::token::TokenKind::*;
+
+        let source = "#!/usr/bin/env parser\n1+2;";
+        let kinds_and_spans: Vec<_> = Lexer::new(source)
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.kind, t.span.start, t.span.end))
+            .collect();
+        assert_eq!(
+            kinds_and_spans,
+            [
+                (Num(1), 22, 23),
+                (Plus, 23, 24),
+                (Num(2), 24, 25),
+                (Semicolon, 25, 26)
+            ]
+        );
+
+        // 改行モードでは、シバンの行末の改行をトークンとして残す
+        let kinds: Vec<_> = Lexer::with_newlines("#!parser\nx")
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(kinds, [Newline, Ident("x".to_string())]);
+    }
+
+    #[test]
+    fn hash_outside_first_line_is_invalid() {
+        for source in ["1+2;\n#!/usr/bin/env parser", " #!parser\n1;", "1 # 2;"] {
+            let e = Lexer::new(source).lex().unwrap_err();
+            assert!(
+                matches!(&e, LexicalError::InvalidToken { token, .. } if token.starts_with('#')),
+                "{}: {:?}",
+                source,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn lex_recover_reports_every_error() {
+        use crate::token::TokenKind::*;
+
+        let (tokens, errors) = Lexer::new("1 @ 2 $ 3").lex_recover();
+        let kinds: Vec<_> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, [Num(1), Num(2), Num(3)]);
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors
+                .iter()
+                .zip(["@", "$"])
+                .all(|(e, c)| matches!(e, LexicalError::InvalidToken { token, .. } if token == c)),
+            "{:?}",
+            errors
+        );
+
+        // 長すぎる数値は1つのエラーにまとめる
+        let source = format!("{} + 1", "9".repeat(100));
+        let (tokens, errors) = Lexer::new(&source).lex_recover();
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(errors[..], [LexicalError::NumberOutOfRange(_)]));
+    }
+
+    #[test]
+    fn increment_and_decrement_tokens() {
+        use crate::token::TokenKind::*;
+
+        let kinds: Vec<_> = Lexer::new("++x + +y --z - -1")
+            .lex()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            [
+                Increment,
+                Ident("x".to_string()),
+                Plus,
+                Plus,
+                Ident("y".to_string()),
+                Decrement,
+                Ident("z".to_string()),
+                Minus,
+                Minus,
+                Num(1),
+            ]
+        );
+    }
+
+    /// トークンのソース上の表記を返す。
+    ///
+    /// 新しいトークンを追加した場合にここを更新し忘れないよう、網羅的にmatchする。
+    fn spelling(kind: &crate::token::TokenKind) -> String {
+        use crate::token::TokenKind::*;
+
+        let s = match kind {
+            Num(n) => return n.to_string(),
+            Ident(name) => return name.clone(),
+            Plus => "+",
+            Minus => "-",
+            Mul => "*",
+            Div => "/",
+            Percent => "%",
+            Pow => "^",
+            Assign => "=",
+            Bang => "!",
+            Increment => "++",
+            Decrement => "--",
+            Eq => "==",
+            Neq => "!=",
+            Gt => ">",
+            Lt => "<",
+            GtEq => ">=",
+            LtEq => "<=",
+            If => "if",
+            Else => "else",
+            While => "while",
+            For => "for",
+            Break => "break",
+            LeftParen => "(",
+            RightParen => ")",
+            LeftBlock => "{",
+            RightBlock => "}",
+            Semicolon => ";",
+            Comma => ",",
+            Newline => "\n",
+        };
+        s.to_string()
+    }
+
+    /// 各トークンの範囲からソースの文字列を切り出し、トークンの表記と一致することを確かめる。
+    fn assert_spans_round_trip(source: &str, tokens: &[Token]) {
+        for tok in tokens {
+            let text = source
+                .get(tok.span.start..tok.span.end)
+                .unwrap_or_else(|| panic!("invalid span {:?} in {:?}", tok.span, source));
+            assert_eq!(text, spelling(&tok.kind), "{:?}", tok);
+        }
+    }
+
+    #[test]
+    fn spans_round_trip() {
+        // 全角空白、マルチバイトの識別子、2文字の演算子、コメントを含む
+        let source = "#!/usr/bin/env parser\n\
+            変数\u{3000}= 12;\tx=-3^2 // コメント\n\
+            if (x >= 1 == 0 != (y <= 2)) { ++x; --y; } else { z = {1,2}; }\n\
+            for (i = 0; i < 10; i = i + 1) { if (i > 5) { break; } }\n\
+            while (!x) { max(abs(x), 2147483647) * 3 / 4; }";
+        let tokens = Lexer::new(source).lex().unwrap();
+        assert!(tokens.len() > 80);
+        assert_spans_round_trip(source, &tokens);
+
+        let source = "x = 1\n\u{3000}\ny = 20";
+        let tokens = Lexer::with_newlines(source).lex().unwrap();
+        assert_spans_round_trip(source, &tokens);
+    }
+
+    #[test]
+    fn raw_identifiers() {
+        use crate::token::TokenKind::*;
+
+        let tokens = Lexer::new("@if = 1; if").lex().unwrap();
+        assert_eq!(tokens[0].kind, Ident("if".to_string()));
+        assert_eq!(tokens[0].span, Span { start: 0, end: 3 });
+        assert_eq!(tokens[4].kind, If);
+
+        // 通常の識別子にも付けられる
+        let tokens = Lexer::new("@x @変数").lex().unwrap();
+        assert_eq!(tokens[0].kind, Ident("x".to_string()));
+        assert_eq!(tokens[1].kind, Ident("変数".to_string()));
+
+        // 識別子が続かない`@`は不正な文字のまま
+        assert!(matches!(
+            Lexer::new("@1").lex(),
+            Err(LexicalError::InvalidToken { token, .. }) if token == "@"
+        ));
+    }
+
+    #[test]
+    fn max_tokens() {
+        // 100万トークンの入力でも、上限を超えた時点で止まる
+        let source = "1+".repeat(500_000);
+        assert_eq!(
+            Lexer::new(&source).with_max_tokens(100).lex(),
+            Err(LexicalError::TooLarge {
+                limit: 100,
+                span: Span {
+                    start: 100,
+                    end: 101
+                },
+            })
+        );
+
+        let (tokens, errors) = Lexer::new(&source).with_max_tokens(100).lex_recover();
+        assert_eq!(tokens.len(), 100);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Too many tokens: the limit is 100");
+
+        // 上限ちょうどは受け付ける
+        assert_eq!(
+            Lexer::new("1 + 2;").with_max_tokens(4).lex().unwrap().len(),
+            4
+        );
+        assert!(Lexer::new("1 + 2;").with_max_tokens(3).lex().is_err());
+    }
+
+    #[test]
+    fn lexer_is_an_iterator() {
+        use crate::token::TokenKind::*;
+
+        let mut lexer = Lexer::new("x = 1; // コメント\n");
+        assert_eq!(
+            lexer.next().map(|t| t.unwrap().kind),
+            Some(Ident("x".to_string()))
+        );
+        assert_eq!(lexer.next().map(|t| t.unwrap().kind), Some(Assign));
+        assert_eq!(lexer.next().map(|t| t.unwrap().kind), Some(Num(1)));
+        assert_eq!(lexer.next().map(|t| t.unwrap().kind), Some(Semicolon));
+        // 終端では`Eof`ではなく`None`を返し、その後も`None`のまま
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.next(), None);
+
+        // エラーの後も読み進める
+        let source = format!("1 @ {} 2", "9".repeat(20));
+        let results: Vec<_> = Lexer::new(&source).map(|t| t.map(|t| t.kind)).collect();
+        assert!(matches!(
+            results[..],
+            [
+                Ok(Num(1)),
+                Err(LexicalError::InvalidToken { .. }),
+                Err(LexicalError::NumberOutOfRange(_)),
+                Ok(Num(2)),
+            ]
+        ));
+    }
+
+    #[test]
+    fn comparison_operator_spans() {
+        use crate::token::TokenKind::{self, *};
+
+        #[rustfmt::skip]
+        let cases: [(&str, TokenKind, usize); 8] = [
+            ("x >= y", GtEq, 2),
+            ("x > y",  Gt,   1),
+            ("x <= y", LtEq, 2),
+            ("x < y",  Lt,   1),
+            ("x == y", Eq,   2),
+            ("x = y",  Assign, 1),
+            ("x != y", Neq,  2),
+            ("!x",     Bang, 1),
+        ];
+        for (source, kind, len) in cases {
+            let tokens = Lexer::new(source).lex().unwrap();
+            let op = tokens.iter().find(|t| t.kind == kind).unwrap();
+            let start = source.find(['>', '<', '=', '!']).unwrap();
+            assert_eq!(
+                op.span,
+                Span {
+                    start,
+                    end: start + len
+                },
+                "{}",
+                source
+            );
+        }
+
+        // 空白を挟まない場合も、2文字目まで含める
+        let tokens = Lexer::new("1>=2<=3").lex().unwrap();
+        assert_eq!(tokens[1].span, Span { start: 1, end: 3 });
+        assert_eq!(tokens[3].span, Span { start: 4, end: 6 });
+        // `> =`は2つのトークン
+        let tokens = Lexer::new("1> =2").lex().unwrap();
+        assert_eq!(
+            (tokens[1].kind.clone(), tokens[1].span.clone()),
+            (Gt, Span { start: 1, end: 2 })
+        );
+        assert_eq!(
+            (tokens[2].kind.clone(), tokens[2].span.clone()),
+            (Assign, Span { start: 3, end: 4 })
+        );
+    }
 }
@@ -0,0 +1,13 @@
+pub mod analyze;
+pub mod ast;
+pub mod backend;
+pub mod codegen;
+pub mod desugar;
+pub mod error;
+pub mod eval;
+pub mod fold;
+pub mod lexer;
+pub mod lint;
+pub mod parser;
+pub mod token;
+pub mod visit;
@@ -0,0 +1,286 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    ast::{BinaryOp, Expression, For, If, Program, Statement, While},
+    eval::is_truthy,
+    fold::const_value,
+    token::{Span, Spanned},
+    visit::{Visitor, walk_expr, walk_program, walk_stmt},
+};
+
+/// 構文上は正しいが、誤りの可能性が高い書き方。
+#[derive(Debug, PartialEq)]
+pub enum Lint {
+    /// `if (x = 0)`のように、条件に代入を直接書いている。`==`の書き間違いの可能性が高い。
+    /// 代入先の変数名を保持する
+    AssignInCondition(String),
+    /// `while (x) {}`のように、本体が空になっている。
+    /// 本体を持つ構文の名前(`if`/`else`/`while`/`for`/`block`)を保持する。
+    ///
+    /// 空の本体は意図して書くこともあるため、`--strict`の場合のみ報告する。
+    EmptyBody(&'static str),
+    /// `while (1) {}`のように、条件が常に真で、本体に`break`がない。
+    /// ループの構文の名前(`while`/`for`)を保持する
+    InfiniteLoop(&'static str),
+    /// `x / (2 - 2)`や`x % 0`のように、除数が0に畳み込める。評価すると必ず0除算になる。
+    /// 除算の式のソース上の範囲を保持する
+    DivisionByZero(Span),
+}
+
+impl Error for Lint {}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AssignInCondition(name) => write!(
+                f,
+                "Assignment to `{}` used as a condition (did you mean `==`?)",
+                name
+            ),
+            Self::EmptyBody(construct) => write!(f, "Empty `{}` body", construct),
+            Self::InfiniteLoop(construct) => write!(
+                f,
+                "`{}` condition is always true and the body has no `break`",
+                construct
+            ),
+            Self::DivisionByZero(_) => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl Lint {
+    /// `--strict`を指定した場合にのみ報告するlintか判定する。
+    pub fn is_opt_in(&self) -> bool {
+        matches!(self, Self::EmptyBody(_))
+    }
+}
+
+impl Spanned for Lint {
+    // 位置情報を持つのは二項演算の式のみのため、それ以外は箇所を示せない
+    fn span(&self) -> Option<Span> {
+        match self {
+            Self::DivisionByZero(span) => Some(span.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// プログラムを検査し、見つかった全ての[`Lint`]を返す。
+///
+/// 代入を比較などで包んだ条件(`if ((x = 0) == 0)`)は意図したものとみなし、対象にしない。
+/// [`Lint::is_opt_in`]のlintも含めて返す。
+pub fn lint(program: &Program) -> Vec<Lint> {
+    let mut linter = Linter::default();
+    walk_program(&mut linter, program);
+    linter.lints
+}
+
+#[derive(Default)]
+struct Linter {
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    fn cond(&mut self, cond: &Expression) {
+        if let Expression::Binary {
+            lhs,
+            op: BinaryOp::Assign,
+            ..
+        } = cond
+            && let Expression::Var(name) = lhs.as_ref()
+        {
+            self.lints.push(Lint::AssignInCondition(name.clone()));
+        }
+    }
+
+    /// 条件を畳み込んだ値が真で、本体から抜け出せないループを報告する。
+    fn infinite_loop(&mut self, construct: &'static str, cond: &Expression, body: &[Statement]) {
+        if const_value(cond).is_some_and(is_truthy) && !has_break(body) {
+            self.lints.push(Lint::InfiniteLoop(construct));
+        }
+    }
+
+    fn body(&mut self, stmt: &Statement) {
+        let bodies: Vec<(&'static str, &[Statement])> = match stmt {
+            Statement::If(If { then, r#else, .. }) => {
+                let mut bodies = vec![("if", then.as_slice())];
+                // `else if`は入れ子のifとして検査する
+                if let Some(r#else) = r#else
+                    && !matches!(r#else.as_slice(), [Statement::If(_)])
+                {
+                    bodies.push(("else", r#else.as_slice()));
+                }
+                bodies
+            }
+            Statement::While(While { body, .. }) => vec![("while", body)],
+            Statement::For(For { body, .. }) => vec![("for", body)],
+            Statement::BlockStatement(body) => vec![("block", body)],
+            Statement::ExpressionStatement(_) | Statement::Break | Statement::Empty => vec![],
+        };
+        for (construct, body) in bodies {
+            if body.is_empty() {
+                self.lints.push(Lint::EmptyBody(construct));
+            }
+        }
+    }
+}
+
+/// 本体がこのループを抜ける`break`を含むか判定する。
+///
+/// 入れ子のループの`break`はそのループを抜けるだけのため数えない。
+/// ブロック式の中には`break`を書けないため、式は調べない。
+fn has_break(body: &[Statement]) -> bool {
+    body.iter().any(|stmt| match stmt {
+        Statement::Break => true,
+        Statement::If(If { then, r#else, .. }) => {
+            has_break(then) || r#else.as_deref().is_some_and(has_break)
+        }
+        Statement::BlockStatement(body) => has_break(body),
+        Statement::ExpressionStatement(_)
+        | Statement::While(_)
+        | Statement::For(_)
+        | Statement::Empty => false,
+    })
+}
+
+impl Visitor for Linter {
+    fn visit_stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::If(If { cond, .. }) => self.cond(cond),
+            Statement::While(While { cond, body }) => {
+                self.cond(cond);
+                self.infinite_loop("while", cond, body);
+            }
+            // 条件を省略したforは本体を実行しないため、無限ループにならない
+            Statement::For(For {
+                cond: Some(cond),
+                body,
+                ..
+            }) => {
+                self.cond(cond);
+                self.infinite_loop("for", cond, body);
+            }
+            _ => {}
+        }
+        self.body(stmt);
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expression) {
+        if let Expression::Binary {
+            op: BinaryOp::Div | BinaryOp::Rem,
+            rhs,
+            span,
+            ..
+        } = expr
+            && const_value(rhs) == Some(0)
+        {
+            self.lints.push(Lint::DivisionByZero(span.clone()));
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn lint_source(input: &str) -> Vec<Lint> {
+        let tokens = Lexer::new(input).lex().unwrap();
+        lint(&Parser::new(tokens).parse().unwrap())
+    }
+
+    #[test]
+    fn assign_in_condition() {
+        let expected = [Lint::AssignInCondition("x".to_string())];
+        assert_eq!(lint_source("if (x = 0) { 1; }"), expected);
+        assert_eq!(lint_source("while (x = 0) { 1; }"), expected);
+        assert_eq!(lint_source("for (; x = 0;) { 1; }"), expected);
+    }
+
+    #[test]
+    fn assign_in_nested_condition() {
+        assert_eq!(
+            lint_source("if (1) { while (y = 1) { 1; } } else if (z = 2) { 1; }"),
+            [
+                Lint::AssignInCondition("y".to_string()),
+                Lint::AssignInCondition("z".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn wrapped_assign_is_allowed() {
+        assert!(lint_source("if ((x = 0) == 0) { 1; }").is_empty());
+        assert!(lint_source("if (x == 0) { 1; }").is_empty());
+        // 初期化式と更新式の代入は正しい使い方
+        assert!(lint_source("for (i = 0; i < 3; i = i + 1) { 1; }").is_empty());
+    }
+
+    #[test]
+    fn empty_body() {
+        assert_eq!(lint_source("while(x){}"), [Lint::EmptyBody("while")]);
+        assert_eq!(lint_source("for (;;) {}"), [Lint::EmptyBody("for")]);
+        assert_eq!(lint_source("{}"), [Lint::EmptyBody("block")]);
+        assert_eq!(
+            lint_source("if (1) {} else {}"),
+            [Lint::EmptyBody("if"), Lint::EmptyBody("else")]
+        );
+        assert_eq!(
+            lint_source("if (1) { 1; } else if (2) {}"),
+            [Lint::EmptyBody("if")]
+        );
+        assert!(lint_source("while (x < 1) { x = 1; }").is_empty());
+        assert!(lint_source("if (1) { 1; } else if (2) { 2; } else { 3; }").is_empty());
+        assert!(Lint::EmptyBody("while").is_opt_in());
+    }
+
+    #[test]
+    fn infinite_loop() {
+        assert_eq!(
+            lint_source("while(1){}"),
+            [Lint::InfiniteLoop("while"), Lint::EmptyBody("while")]
+        );
+        assert_eq!(
+            lint_source("for (i = 0; 2 > 1; i = i + 1) { x = i; }"),
+            [Lint::InfiniteLoop("for")]
+        );
+        // 入れ子のループのbreakは外側のループを抜けない
+        assert_eq!(
+            lint_source("while (1) { while (x) { break; } }"),
+            [Lint::InfiniteLoop("while")]
+        );
+        assert!(lint_source("for (i = 0; 1; i = i + 1) { if (i >= 5) { break; } }").is_empty());
+        assert!(lint_source("while (1) { { break; } }").is_empty());
+        assert!(lint_source("while (x) { 1; }").is_empty());
+        assert!(lint_source("while (0) { 1; }").is_empty());
+        assert!(lint_source("for (;;) { 1; }").is_empty());
+        assert!(!Lint::InfiniteLoop("while").is_opt_in());
+    }
+
+    #[test]
+    fn division_by_zero() {
+        // 除算の式のソースを返す
+        fn divisions(input: &str) -> Vec<&str> {
+            lint_source(input)
+                .iter()
+                .map(|lint| {
+                    let span = lint.span().unwrap();
+                    &input[span.start..span.end]
+                })
+                .collect()
+        }
+
+        assert_eq!(divisions("1/0;"), ["1/0"]);
+        assert_eq!(divisions("y = x / (2-2);"), ["x / (2-2)"]);
+        assert_eq!(divisions("if (1) { y = { 1 / -0 }; }"), ["1 / -0"]);
+        assert_eq!(divisions("x % (1 - 1);"), ["x % (1 - 1)"]);
+        assert!(lint_source("x / y;").is_empty());
+        assert!(lint_source("0 / 2;").is_empty());
+
+        let lint = Lint::DivisionByZero(Span { start: 0, end: 3 });
+        assert_eq!(lint.to_string(), "Division by zero");
+        assert!(!lint.is_opt_in());
+    }
+}
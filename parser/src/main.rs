@@ -1,20 +1,19 @@
-mod ast;
-mod codegen;
-mod error;
-mod lexer;
-mod parser;
-mod token;
-
 use std::{
     fs::File,
-    io::Write,
+    io::{self, IsTerminal, Read, Write},
     process::{Command, ExitCode},
+    str::FromStr,
 };
 
-use crate::{
-    codegen::CodeGenerator,
+use parser::{
+    analyze::build_cfg,
+    ast::Program,
+    backend::BackendKind,
+    desugar::desugar,
     error::{CompilerError, format_error},
+    eval::{ArithMode, Evaluator, Value},
     lexer::Lexer,
+    lint::lint,
     parser::Parser,
 };
 
@@ -23,158 +22,595 @@ use crate::{
 // TODO: return文のサポート
 // TODO: 関数呼び出しサポート
 // TODO: テスト再設計
-// TODO: for文サポート
 // TODO: while文サポート
 // TODO: statement系でblock statement以外のbodyをパースできるようにする
-fn run(input: &str) -> Result<(), CompilerError> {
-    let tokens = Lexer::new(input).lex()?;
+fn run(input: &str, options: &Options) -> Result<(), CompilerError> {
+    let tokens = lexer(input, options).lex()?;
     let program = Parser::new(tokens).parse()?;
-    let assembly_string = CodeGenerator::new().generate(&program);
+    if let Some(e) = check_lints(&program, input, options).into_iter().next() {
+        return Err(e);
+    }
+    let program = desugar(program);
+    let mut backend = options.backend.backend();
+    let output = backend.emit(&program);
 
-    let mut f = File::create("test.s").unwrap();
-    f.write_all(assembly_string.as_bytes()).unwrap();
+    let source_file = format!("test.{}", backend.extension());
+    let mut f = File::create(&source_file).unwrap();
+    f.write_all(output.as_bytes()).unwrap();
 
     // Create object file
     let _ = Command::new("cc")
         .arg("-o")
         .arg("test")
-        .arg("test.s")
+        .arg(&source_file)
         .output()
         .expect("failed to execute process");
 
     Ok(())
 }
 
-fn main() -> ExitCode {
-    // 引数で式が与えられた場合はそれを入力として扱う
-    // それ以外は標準入力にフォールバックする
-    let arg = std::env::args().nth(1);
-    let input = arg.unwrap_or_else(|| {
-        let mut buf = String::new();
-        std::io::stdin()
-            .read_line(&mut buf)
-            .expect("Failed to read input");
-        buf.trim_end().to_owned()
-    });
-
-    run(&input)
-        .inspect_err(|e| eprintln!("{}", format_error(e, &input)))
-        .map_or(ExitCode::FAILURE, |_| ExitCode::SUCCESS)
+/// `--max-tokens`の上限を設定したレキサーを作る。
+fn lexer<'a>(input: &'a str, options: &Options) -> Lexer<'a> {
+    let lexer = Lexer::new(input);
+    match options.max_tokens {
+        Some(limit) => lexer.with_max_tokens(limit),
+        None => lexer,
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{parser::SyntaxError, token::TokenKind::*};
+/// 評価器でプログラムを実行し、その値を返す。
+fn eval(input: &str, options: &Options) -> Result<Value, CompilerError> {
+    let tokens = lexer(input, options).lex()?;
+    let program = Parser::new(tokens).parse()?;
+    if let Some(e) = check_lints(&program, input, options).into_iter().next() {
+        return Err(e);
+    }
+    let mut evaluator = Evaluator::with_mode(options.arith);
+    if let Some(limit) = options.max_iterations {
+        evaluator = evaluator.with_max_iterations(limit);
+    }
+    Ok(evaluator.eval_value(&program)?)
+}
 
-    fn parse(input: &str) -> Result<i32, CompilerError> {
-        let mut lexer = Lexer::new(input);
-        let tokens = lexer.lex()?;
-        let expr = Parser::new(tokens).parse()?;
-        let mut evaluator = Evaluator::new();
+/// プログラムの制御フローグラフをGraphvizの`dot`形式で返す。
+fn cfg(input: &str, options: &Options) -> Result<String, CompilerError> {
+    let tokens = lexer(input, options).lex()?;
+    let program = Parser::new(tokens).parse()?;
+    Ok(build_cfg(&program).to_dot())
+}
 
-        Ok(evaluator.eval(&expr))
+/// 字句解析と構文解析のみを行い、見つかった全てのエラーを返す。
+///
+/// 字句エラーがある場合は構文解析を行わない。構文エラーがない場合は[`check_lints`]も行う。
+fn check(input: &str, options: &Options) -> Vec<CompilerError> {
+    let (tokens, errors) = lexer(input, options).lex_recover();
+    if !errors.is_empty() {
+        return errors.into_iter().map(CompilerError::from).collect();
+    }
+    let (program, errors) = Parser::new(tokens).parse_recover();
+    if !errors.is_empty() {
+        return errors.into_iter().map(CompilerError::from).collect();
     }
 
-    #[test]
-    fn sum() {
-        let result = parse("1 + 2;");
-        assert_eq!(result, Ok(3));
+    check_lints(&program, input, options)
+}
+
+/// lintを行う。
+///
+/// `--strict`の場合は見つかった警告をエラーとして返し、
+/// それ以外の場合は警告を標準エラー出力に表示して空の`Vec`を返す。
+/// [`Lint::is_opt_in`]の警告は`--strict`の場合のみ報告する。
+fn check_lints(program: &Program, input: &str, options: &Options) -> Vec<CompilerError> {
+    let lints = lint(program);
+    if options.strict {
+        return lints.into_iter().map(CompilerError::from).collect();
     }
 
-    #[test]
-    fn difference() {
-        let result = parse("1 - 2 - 3;");
-        assert_eq!(result, Ok(-4));
+    for l in lints.iter().filter(|l| !l.is_opt_in()) {
+        eprintln!("Warning: {}", format_error(l, input, options.colored()));
     }
+    vec![]
+}
 
-    #[test]
-    fn sum_3_operand() {
-        let result = parse("1 + 2 + 3;");
-        assert_eq!(result, Ok(6));
+/// ソースコードを読み込む。
+///
+/// 引数で式が与えられた場合はそれを入力として扱い、
+/// それ以外は`reader`(通常は標準入力)を末尾まで読む。
+/// どちらの場合も末尾の空白・改行は取り除く。
+fn read_source(arg: Option<String>, mut reader: impl Read) -> io::Result<String> {
+    let source = match arg {
+        Some(arg) => arg,
+        None => {
+            let mut buf = String::new();
+            reader.read_to_string(&mut buf)?;
+            buf
+        }
+    };
+
+    Ok(source.trim_end().to_owned())
+}
+
+/// コマンドライン引数
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    /// `--check`: 構文チェックのみを行い、コード生成をしない
+    check: bool,
+    /// `--strict`: lintの警告をエラーとして扱う
+    strict: bool,
+    /// `--pretty-errors`: エラーを色付きで表示する
+    pretty_errors: bool,
+    /// `--backend=arm64|c`: コード生成に使うバックエンド
+    backend: BackendKind,
+    /// `--emit=asm|eval|cfg`: 出力の種類
+    emit: Emit,
+    /// `--radix=dec|hex|bin`: `--emit=eval`で表示する値の基数
+    radix: Radix,
+    /// `--grouped`: `--emit=eval`で表示する10進数を3桁ごとに`,`で区切る
+    grouped: bool,
+    /// `--max-iterations=N`: `--emit=eval`でループの反復回数の合計をN回までに制限する。`None`の場合は制限しない
+    max_iterations: Option<usize>,
+    /// `--max-tokens=N`: 入力のトークン数をN個までに制限する。`None`の場合は制限しない
+    max_tokens: Option<usize>,
+    /// `--arith=wrapping|checked`: `--emit=eval`で算術演算がオーバーフローした場合の振る舞い
+    arith: ArithMode,
+    /// 引数で与えられた式。`None`の場合は標準入力から読む
+    input: Option<String>,
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        for arg in args {
+            match arg.as_str() {
+                "--check" => options.check = true,
+                "--strict" => options.strict = true,
+                "--pretty-errors" => options.pretty_errors = true,
+                "--grouped" => options.grouped = true,
+                flag if flag.starts_with("--backend=") => {
+                    let name = &flag["--backend=".len()..];
+                    options.backend = name
+                        .parse()
+                        .map_err(|_| format!("Unknown backend: {}", name))?;
+                }
+                flag if flag.starts_with("--emit=") => {
+                    let name = &flag["--emit=".len()..];
+                    options.emit = name
+                        .parse()
+                        .map_err(|_| format!("Unknown emit kind: {}", name))?;
+                }
+                flag if flag.starts_with("--radix=") => {
+                    let name = &flag["--radix=".len()..];
+                    options.radix = name
+                        .parse()
+                        .map_err(|_| format!("Unknown radix: {}", name))?;
+                }
+                flag if flag.starts_with("--max-tokens=") => {
+                    let n = &flag["--max-tokens=".len()..];
+                    options.max_tokens = Some(
+                        n.parse()
+                            .map_err(|_| format!("Invalid token limit: {}", n))?,
+                    );
+                }
+                flag if flag.starts_with("--arith=") => {
+                    let name = &flag["--arith=".len()..];
+                    options.arith = name
+                        .parse()
+                        .map_err(|_| format!("Unknown arith mode: {}", name))?;
+                }
+                flag if flag.starts_with("--max-iterations=") => {
+                    let n = &flag["--max-iterations=".len()..];
+                    options.max_iterations = Some(
+                        n.parse()
+                            .map_err(|_| format!("Invalid iteration limit: {}", n))?,
+                    );
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+                _ => options.input = Some(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// エラーを色付きで表示するか。
+    ///
+    /// `--pretty-errors`が指定されていても、標準エラー出力が端末でない場合は
+    /// エスケープシーケンスがファイルなどに混ざらないよう無効にする。
+    fn colored(&self) -> bool {
+        self.pretty_errors && io::stderr().is_terminal()
     }
+}
 
-    #[test]
-    fn prod_3_operand() {
-        let result = parse("1*2*3;");
-        assert_eq!(result, Ok(6));
+/// 何を出力するか。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Emit {
+    /// バックエンドでコードを生成し、`cc`でビルドする
+    #[default]
+    Asm,
+    /// 評価器でプログラムを実行し、値を表示する
+    Eval,
+    /// 制御フローグラフをGraphvizの`dot`形式で表示する
+    Cfg,
+}
+
+impl FromStr for Emit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(Self::Asm),
+            "eval" => Ok(Self::Eval),
+            "cfg" => Ok(Self::Cfg),
+            _ => Err(()),
+        }
     }
+}
 
-    #[test]
-    fn process_with_priority() {
-        let result = parse("1+2*3;");
-        assert_eq!(result, Ok(7));
+/// `--emit=eval`で表示する値の基数。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+}
+
+impl FromStr for Radix {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dec" => Ok(Self::Dec),
+            "hex" => Ok(Self::Hex),
+            "bin" => Ok(Self::Bin),
+            _ => Err(()),
+        }
     }
+}
 
-    #[test]
-    fn without_space() {
-        let result = parse("1+2;");
-        assert_eq!(result, Ok(3));
+impl Radix {
+    /// `0x`/`0b`のプレフィックスを付けて表示する。
+    ///
+    /// 負の数は2の補数ではなく、符号と絶対値で表示する(例: `-255`は`-0xff`)。
+    fn format(self, n: i32) -> String {
+        let sign = if n < 0 { "-" } else { "" };
+        let abs = n.unsigned_abs();
+        match self {
+            Self::Dec => n.to_string(),
+            Self::Hex => format!("{}{:#x}", sign, abs),
+            Self::Bin => format!("{}{:#b}", sign, abs),
+        }
+    }
+
+    /// 整数は[`Radix::format`]で、真偽値は基数によらず`true`/`false`と表示する。
+    ///
+    /// `grouped`の場合、10進数を3桁ごとに`,`で区切る(例: `1,000,000`)。16進数と2進数には影響しない。
+    fn format_value(self, v: Value, grouped: bool) -> String {
+        match v {
+            Value::Int(n) if grouped && self == Self::Dec => group_thousands(n),
+            Value::Int(n) => self.format(n),
+            Value::Bool(_) => v.to_string(),
+        }
     }
+}
 
-    #[test]
-    fn with_paren() {
-        let result = parse("(1+2);");
-        assert_eq!(result, Ok(3));
+/// 10進数を3桁ごとに`,`で区切って表示する。
+fn group_thousands(n: i32) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if n < 0 {
+        grouped.push('-');
+    }
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
     }
+    grouped
+}
+
+fn main() -> ExitCode {
+    let mut options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let input = match read_source(options.input.take(), std::io::stdin()) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("Failed to read input: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if options.check {
+        let errors = check(&input, &options);
+        for e in &errors {
+            eprintln!("{}", format_error(e, &input, options.colored()));
+        }
+        return if errors.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    if options.emit == Emit::Eval {
+        return eval(&input, &options)
+            .inspect(|v| println!("{}", options.radix.format_value(*v, options.grouped)))
+            .inspect_err(|e| eprintln!("{}", format_error(e, &input, options.colored())))
+            .map_or(ExitCode::FAILURE, |_| ExitCode::SUCCESS);
+    }
+
+    if options.emit == Emit::Cfg {
+        return cfg(&input, &options)
+            .inspect(|dot| print!("{}", dot))
+            .inspect_err(|e| eprintln!("{}", format_error(e, &input, options.colored())))
+            .map_or(ExitCode::FAILURE, |_| ExitCode::SUCCESS);
+    }
+
+    run(&input, &options)
+        .inspect_err(|e| eprintln!("{}", format_error(e, &input, options.colored())))
+        .map_or(ExitCode::FAILURE, |_| ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{
+        eval::calc, fold::fold, lint::Lint, parser::SyntaxError, tok, token::TokenKind::*,
+    };
+
+    fn parse(input: &str) -> Result<i32, CompilerError> {
+        calc(input)
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
     #[test]
-    fn with_paren_precedence() {
-        let result = parse("(1+2)*3;");
-        assert_eq!(result, Ok(9));
+    fn read_source_from_arg() {
+        let source = read_source(Some("1 + 2;\n".to_string()), io::empty());
+        assert_eq!(source.unwrap(), "1 + 2;");
     }
 
     #[test]
-    fn power() {
-        let result = parse("10^2;");
-        assert_eq!(result, Ok(100));
+    fn read_source_multiline_stdin() {
+        let stdin = "x = 1;\nwhile (x < 10) {\n  x = x * 2;\n}\nx;\n\n".as_bytes();
+        let source = read_source(None, stdin).unwrap();
+
+        assert_eq!(source, "x = 1;\nwhile (x < 10) {\n  x = x * 2;\n}\nx;");
+        assert_eq!(calc(&source), Ok(16));
     }
 
     #[test]
-    fn gt_true() {
-        let result = parse("1>0;");
-        assert_eq!(result, Ok(1));
+    fn read_source_invalid_utf8() {
+        let result = read_source(None, &[0xff, 0xfe][..]);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
     }
 
     #[test]
-    fn gt_false() {
-        let result = parse("1>2;");
-        assert_eq!(result, Ok(0));
+    fn radix_format() {
+        assert_eq!(Radix::Dec.format(255), "255");
+        assert_eq!(Radix::Hex.format(255), "0xff");
+        assert_eq!(Radix::Bin.format(5), "0b101");
+        assert_eq!(Radix::Hex.format(-255), "-0xff");
+        assert_eq!(Radix::Hex.format(i32::MIN), "-0x80000000");
+        assert_eq!(Radix::Bin.format(0), "0b0");
     }
 
     #[test]
-    fn gt_eq_true() {
-        let result = parse("1>=1;");
-        assert_eq!(result, Ok(1));
+    fn grouped_format() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1000), "1,000");
+        assert_eq!(group_thousands(1000000), "1,000,000");
+        assert_eq!(group_thousands(-1234567), "-1,234,567");
+        assert_eq!(group_thousands(i32::MIN), "-2,147,483,648");
+
+        assert_eq!(Radix::Dec.format_value(Value::Int(12345), true), "12,345");
+        assert_eq!(Radix::Dec.format_value(Value::Int(12345), false), "12345");
+        assert_eq!(Radix::Hex.format_value(Value::Int(65535), true), "0xffff");
+        assert_eq!(Radix::Dec.format_value(Value::Bool(true), true), "true");
     }
 
     #[test]
-    fn gt_eq_false() {
-        let result = parse("1>=2;");
-        assert_eq!(result, Ok(0));
+    fn options() {
+        assert_eq!(
+            Options::parse(args(&["--check", "1;"])),
+            Ok(Options {
+                check: true,
+                input: Some("1;".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Options::parse(args(&["--strict"])),
+            Ok(Options {
+                strict: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Options::parse(args(&["--pretty-errors"])),
+            Ok(Options {
+                pretty_errors: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Options::parse(args(&["--backend=c"])),
+            Ok(Options {
+                backend: BackendKind::C,
+                ..Default::default()
+            })
+        );
+        assert!(Options::parse(args(&["--backend=x86"])).is_err());
+        assert_eq!(
+            Options::parse(args(&["--emit=eval", "--radix=hex"])),
+            Ok(Options {
+                emit: Emit::Eval,
+                radix: Radix::Hex,
+                ..Default::default()
+            })
+        );
+        assert!(Options::parse(args(&["--radix=oct"])).is_err());
+        assert_eq!(
+            Options::parse(args(&["--emit=eval", "--grouped"])),
+            Ok(Options {
+                emit: Emit::Eval,
+                grouped: true,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            Options::parse(args(&["--emit=eval", "--max-iterations=1000"])),
+            Ok(Options {
+                emit: Emit::Eval,
+                max_iterations: Some(1000),
+                ..Default::default()
+            })
+        );
+        assert!(Options::parse(args(&["--max-iterations=-1"])).is_err());
+        assert!(Options::parse(args(&["--max-iterations="])).is_err());
+        assert_eq!(
+            Options::parse(args(&["--emit=eval", "--arith=wrapping"])),
+            Ok(Options {
+                emit: Emit::Eval,
+                arith: ArithMode::Wrapping,
+                ..Default::default()
+            })
+        );
+        assert!(Options::parse(args(&["--arith=saturating"])).is_err());
+        assert_eq!(
+            Options::parse(args(&["--check", "--max-tokens=100"])),
+            Ok(Options {
+                check: true,
+                max_tokens: Some(100),
+                ..Default::default()
+            })
+        );
+        assert!(Options::parse(args(&["--max-tokens=many"])).is_err());
+        assert_eq!(Options::parse(args(&[])), Ok(Options::default()));
+        assert!(Options::parse(args(&["--unknown"])).is_err());
     }
 
     #[test]
-    fn lt_true() {
-        let result = parse("1<2;");
-        assert_eq!(result, Ok(1));
+    fn check_reports_all_errors() {
+        assert!(check("x = 1; x;", &Options::default()).is_empty());
+        assert_eq!(check("1 + * 2; 3 + ) 4;", &Options::default()).len(), 2);
+        assert_eq!(check("1 @ 2;", &Options::default()).len(), 1);
     }
 
     #[test]
-    fn lt_false() {
-        let result = parse("1<0;");
-        assert_eq!(result, Ok(0));
+    fn check_strict_reports_lints() {
+        let strict = Options {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(check("if (x = 0) { 1; }", &Options::default()).is_empty());
+        assert_eq!(
+            check("if (x = 0) { 1; }", &strict),
+            [CompilerError::Lint(Lint::AssignInCondition(
+                "x".to_string()
+            ))]
+        );
+        assert!(check("if ((x = 0) == 0) { 1; }", &strict).is_empty());
     }
 
     #[test]
-    fn lt_eq_true() {
-        let result = parse("1<=1;");
-        assert_eq!(result, Ok(1));
+    fn check_reports_every_lexical_error() {
+        let errors = check("1 @ 2 $ 3;", &Options::default());
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors
+                .iter()
+                .all(|e| matches!(e, CompilerError::Lexical(_)))
+        );
     }
 
     #[test]
-    fn lt_eq_false() {
-        let result = parse("1<=0;");
-        assert_eq!(result, Ok(0));
+    fn empty_body_is_reported_only_under_strict() {
+        let strict = Options {
+            strict: true,
+            ..Default::default()
+        };
+        assert!(check("while(x){}", &Options::default()).is_empty());
+        assert_eq!(
+            check("while(x){}", &strict),
+            [CompilerError::Lint(Lint::EmptyBody("while"))]
+        );
+        assert!(check("while(x){ 1; }", &strict).is_empty());
+    }
+
+    /// 正しく評価できるサンプルプログラムと、その値
+    #[rustfmt::skip]
+    const TESTS: &[(&str, &str, i32)] = &[
+        ("sum",                   "1 + 2;",                                                                   3),
+        ("difference",            "1 - 2 - 3;",                                                               -4),
+        ("sum_3_operand",         "1 + 2 + 3;",                                                               6),
+        ("prod_3_operand",        "1*2*3;",                                                                   6),
+        ("process_with_priority", "1+2*3;",                                                                   7),
+        ("without_space",         "1+2;",                                                                     3),
+        ("with_paren",            "(1+2);",                                                                   3),
+        ("with_paren_precedence", "(1+2)*3;",                                                                 9),
+        ("power",                 "10^2;",                                                                    100),
+        ("power_of_two",          "2^10;",                                                                    1024),
+        ("power_right_assoc",     "2^3^2;",                                                                   512),
+        ("remainder",             "7 % 3;",                                                                   1),
+        ("remainder_then_plus",   "10 % 2 + 1;",                                                              1),
+        ("remainder_precedence",  "2 + 6 % 4;",                                                               4),
+        ("gt_true",               "1>0;",                                                                     1),
+        ("gt_false",              "1>2;",                                                                     0),
+        ("gt_eq_true",            "1>=1;",                                                                    1),
+        ("gt_eq_false",           "1>=2;",                                                                    0),
+        ("lt_true",               "1<2;",                                                                     1),
+        ("lt_false",              "1<0;",                                                                     0),
+        ("lt_eq_true",            "1<=1;",                                                                    1),
+        ("lt_eq_false",           "1<=0;",                                                                    0),
+        ("unary_minus",           "-1;",                                                                      -1),
+        ("line_comment",          "1 + 2; // ignored",                                                        3),
+        ("assignment",            "x=2; x;",                                                                  2),
+        ("if_statement",          "x=0; if (1>=0) {x=2;} x;",                                                 2),
+        ("if_else_statement",     "if (0) {} else {x=1;} x;",                                                 1),
+        ("else_if_statement",     "x=2; if (x==1) {y=10;} else if (x==2) {y=20;} else {y=30;} y;",            20),
+        ("while_statement",       "x=0; while(x<1){x=1;} x;",                                                 1),
+        ("for_statement",         "for (ans=i=0; i<10; i=i+1) {ans = ans + i;} ans;",                         45),
+        ("for_with_empty_clause", "for (x=0;;) { x=1; } x;",                                                  0),
+        ("fibonatti",             "n=10; a=0; b=1; for (i=0; i<n; i=i+1) { tmp = a; a = b; b = tmp + b;} a;", 55),
+        ("block_statement",       "{ foo = 1; } foo;",                                                        1),
+        ("empty_statement",       ";",                                                                        0),
+        ("block_then_semicolon",  "{x=1;}; x;",                                                               1),
+        ("trailing_semicolons",   "x=1;;;",                                                                   0),
+        ("eq_true",               "1==1;",                                                                    1),
+        ("eq_false",              "1==0;",                                                                    0),
+        ("neq_true",              "1!=0;",                                                                    1),
+        ("neq_false",             "1!=1;",                                                                    0),
+        ("raw_identifier",        "@if = 1; @if;",                                                            1),
+        ("raw_keyword_in_if",     "@while = 2; if (@while) { @while + 1; }",                                  3),
+    ];
+
+    #[test]
+    fn sample_programs() {
+        for (name, input, expected) in TESTS {
+            assert_eq!(parse(input), Ok(*expected), "{}: {}", name, input);
+        }
+    }
+
+    /// 定数畳み込みをしてから評価しても、同じ値になることを確かめる
+    #[test]
+    fn fold_agrees_with_evaluator() {
+        for (name, input, _) in TESTS {
+            let tokens = Lexer::new(input).lex().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            let expected = Evaluator::new().eval(&program);
+            let folded = Evaluator::new().eval(&fold(program));
+            assert_eq!(folded, expected, "{}: {}", name, input);
+        }
     }
 
     #[test]
@@ -195,12 +631,6 @@ mod tests {
         );
     }
 
-    #[test]
-    fn unary_minus() {
-        let result = parse("-1;");
-        assert_eq!(result, Ok(-1));
-    }
-
     #[test]
     fn unexpected_eof() {
         let result = parse("-");
@@ -208,81 +638,67 @@ mod tests {
     }
 
     #[test]
-    fn assignment() {
-        let result = parse("x=2; x;");
-        assert_eq!(result, Ok(2));
-    }
-
-    #[test]
-    fn invalid_assignment() {
-        let result = parse("1=2;");
-        assert_eq!(
-            result,
-            Err(SyntaxError::InvalidAssignmentTarget(tok!(Assign, 1, 2)).into())
-        );
-    }
-
-    #[test]
-    fn if_statement() {
-        let result = parse("x=0; if (1>=0) {x=2;} x;");
-
-        assert_eq!(result, Ok(2),);
+    fn empty_input() {
+        let result = parse("");
+        assert_eq!(result, Err(SyntaxError::UnexpectedEof.into()));
     }
 
     #[test]
-    fn while_statement() {
-        let result = parse("x=0; while(x<1){x=1;} x;");
-
-        assert_eq!(result, Ok(1),);
+    fn whitespace_only_input() {
+        let result = parse("   ");
+        assert_eq!(result, Err(SyntaxError::UnexpectedEof.into()));
     }
 
     #[test]
-    fn for_statement() {
-        let result = parse("for (ans=i=0; i<10; i=i+1) {ans = ans + i;} ans;");
-
-        assert_eq!(result, Ok(45),);
+    fn empty_input_error_message() {
+        let err = run("", &Options::default()).unwrap_err();
+        assert_eq!(
+            format_error(&err, "", false),
+            "Syntax error: Unexpected end of file"
+        );
     }
 
     #[test]
-    fn for_with_empty_clause() {
-        let result = parse("for (x=0;;) { x=1; } x;");
-        assert_eq!(result, Ok(0));
+    fn comment_only() {
+        let result = parse("// all comment");
+        assert_eq!(result, Err(SyntaxError::UnexpectedEof.into()));
     }
 
     #[test]
-    fn fibonatti() {
-        let result =
-            parse("n=10; a=0; b=1; for (i=0; i<n; i=i+1) { tmp = a; a = b; b = tmp + b;} a;");
-        assert_eq!(result, Ok(55));
+    fn bang_is_not_an_expression() {
+        let result = parse("!0;");
+        assert_eq!(
+            result,
+            Err(SyntaxError::ExpectedExpression(tok!(Bang, 0, 1)).into())
+        );
     }
 
     #[test]
-    fn block_statement() {
-        let result = parse("{ foo = 1; } foo;");
-        assert_eq!(result, Ok(1));
+    fn invalid_assignment() {
+        let result = parse("1=2;");
+        assert_eq!(
+            result,
+            Err(SyntaxError::InvalidAssignmentTarget(tok!(Assign, 1, 2)).into())
+        );
     }
 
     #[test]
-    fn eq_true() {
-        let result = parse("1==1;");
+    fn else_binds_to_nearest_if() {
+        // 内側のifのelseとして扱われる。外側のifは条件が偽なので何も実行しない
+        let result = parse("x=0; if (1) { if (0) {} else {x=1;} } x;");
         assert_eq!(result, Ok(1));
-    }
 
-    #[test]
-    fn eq_false() {
-        let result = parse("1==0;");
+        let result = parse("x=0; if (0) { if (1) {} else {x=1;} } x;");
         assert_eq!(result, Ok(0));
     }
 
     #[test]
-    fn neq_true() {
-        let result = parse("1!=0;");
-        assert_eq!(result, Ok(1));
-    }
-
-    #[test]
-    fn neq_false() {
-        let result = parse("1!=1;");
-        assert_eq!(result, Ok(0));
+    fn if_body_requires_braces() {
+        // 本体に`{ }`が必須のため、`if (a) if (b) {} else {}`は曖昧にならず構文エラーになる
+        let result = parse("if (1) if (0) {} else {}");
+        assert_eq!(
+            result,
+            Err(SyntaxError::UnexpectedToken(tok!(If, 7, 9)).into())
+        );
     }
 }
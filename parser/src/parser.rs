@@ -1,7 +1,10 @@
-use std::{error::Error, fmt, iter::Peekable};
+use std::{collections::HashMap, error::Error, fmt, iter::Peekable};
 
 use crate::{
-    ast::{Assoc, BinaryOp, Expression, For, If, Program, Statement, UnaryOp, While, prec},
+    ast::{
+        Assoc, BinaryOp, Builtin, Expression, For, If, OpInfo, Program, Statement, UnaryOp, While,
+        prec,
+    },
     token::{Span, Spanned, Token, TokenKind},
 };
 
@@ -10,6 +13,16 @@ pub enum SyntaxError {
     UnmatchedLeftParen(Token),
     UnexpectedToken(Token),
     InvalidAssignmentTarget(Token),
+    /// 式が始まるべき位置に、式を始められないトークン(`)`や`;`など)があった
+    ExpectedExpression(Token),
+    /// 組み込み関数にない名前を呼び出した。関数名のトークンを保持する
+    UnknownFunction(Token),
+    /// 組み込み関数の引数の数が[`Builtin::arity`]と異なる。関数名のトークンを保持する
+    WrongArity {
+        tok: Token,
+        func: Builtin,
+        found: usize,
+    },
     UnexpectedEof,
 }
 
@@ -23,6 +36,17 @@ impl fmt::Display for SyntaxError {
             Self::InvalidAssignmentTarget(tok) => {
                 write!(f, "Invalid assignment target: {:?}", tok.kind)
             }
+            Self::ExpectedExpression(tok) => {
+                write!(f, "Expected an expression, found {:?}", tok.kind)
+            }
+            Self::UnknownFunction(tok) => write!(f, "Unknown function: {:?}", tok.kind),
+            Self::WrongArity { func, found, .. } => write!(
+                f,
+                "`{}` takes {} argument(s), but {} given",
+                func.name(),
+                func.arity(),
+                found
+            ),
             Self::UnexpectedEof => write!(f, "Unexpected end of file"),
         }
     }
@@ -33,7 +57,10 @@ impl Spanned for SyntaxError {
         match self {
             Self::UnmatchedLeftParen(tok)
             | Self::UnexpectedToken(tok)
-            | Self::InvalidAssignmentTarget(tok) => Some(tok.span.clone()),
+            | Self::InvalidAssignmentTarget(tok)
+            | Self::ExpectedExpression(tok)
+            | Self::UnknownFunction(tok)
+            | Self::WrongArity { tok, .. } => Some(tok.span.clone()),
             Self::UnexpectedEof => None,
         }
     }
@@ -41,12 +68,15 @@ impl Spanned for SyntaxError {
 
 pub type ParseResult<T> = Result<T, SyntaxError>;
 
+/// 二項演算子の優先度と結合順序の表。[`Parser::with_table`]で既定の[`BinaryOp::op_info`]を上書きする。
+pub type PrecedenceTable = HashMap<BinaryOp, OpInfo>;
+
 /// 計算式を構文解析し、[`Expression`]を構築するパーサー。
 ///
 /// ## 仕様
 /// ### サポートする演算子
 ///
-/// - 二項演算子: `+`, `-`, `*`, `/`, `^`, `>`, `<`, `>=`, `<=`, `=`, `==`, `!=`
+/// - 二項演算子: `+`, `-`, `*`, `/`, `%`, `^`, `>`, `<`, `>=`, `<=`, `=`, `==`, `!=`
 /// - 単項演算子: `-`
 ///
 /// ### 優先順位
@@ -55,7 +85,7 @@ pub type ParseResult<T> = Result<T, SyntaxError>;
 /// 1. `=`
 /// 2. `<` `<=` `>` `>=` `==` `!=`
 /// 3. `+` `-`
-/// 4. `*` `/`
+/// 4. `*` `/` `%`
 /// 5. 単項`-`
 /// 6. `^`
 /// 7. `(` `)`
@@ -68,17 +98,35 @@ pub type ParseResult<T> = Result<T, SyntaxError>;
 /// ### 文法
 ///
 /// Program -> Stmt { Stmt }
-/// Stmt    -> If | While | For | E ";"
-/// If      -> "if" "(" E ")" "{" { Stmt } "}"
+/// Stmt    -> If | While | For | Break | E Term | ";"
+/// Term    -> ";"
+/// If      -> "if" "(" E ")" "{" { Stmt } "}" [ "else" ( If | "{" { Stmt } "}" ) ]
 /// While   -> "while" "(" E ")" "{" { Stmt } "}"
 /// For     -> "for" "(" [ E ] ";" [ E ] ";" [ E ] ")" "{" { Stmt } "}"
+/// Break   -> "break" Term
+///
+/// `;`のみの文は何もしない空文とする。`{ x = 1; };`の`;`や`;;`も空文として読む。
+///
+/// `break`はループの本体の中にのみ書ける。ブロック式の中では、ループの中であっても書けない。
 ///
 /// E       -> Expr(0)
 /// Expr(p) -> Primary { BinOp Expr(q) }
-/// Primary -> Unary Expr(q) | "(" E ")" | Ident | v
-/// Ident   -> letter { letter | unicode_digit }
-/// BinOp   -> "=" | "+" | "-" | "*" | "/" | "^" | ">" | "<" | ">=" | "<=" | "==" | "!="
+/// Primary -> Unary Expr(q) | IncDec Primary | "(" E ")" | Block | Call | Ident | v
+/// Block   -> "{" { Stmt } [ E ] "}"
+/// Call    -> Ident "(" [ E { "," E } ] ")"
+/// Ident   -> [ "@" ] letter { letter | unicode_digit }
+/// BinOp   -> "=" | "+" | "-" | "*" | "/" | "%" | "^" | ">" | "<" | ">=" | "<=" | "==" | "!="
 /// Unary   -> "-"
+/// IncDec  -> "++" | "--"
+///
+/// `++x`/`--x`は`x = x + 1`/`x = x - 1`と同じ代入式として扱い、値は更新後の値になる。
+/// 対象は変数でなければならない。後置の`x++`はサポートしない。
+/// `--5`は二重の単項`-`ではなく`--`として字句解析されるため、`- -5`と書く。
+///
+/// 呼び出せるのは[`Builtin`]の組み込み関数(`abs`/`min`/`max`)のみ。
+/// 関数名と同じ名前の変数も使え、直後に`(`が続く場合のみ呼び出しとする。
+///
+/// `@`を前置した識別子(`@if`など)は、キーワードと同じ名前の変数として扱う。
 ///
 /// ### AST の構造
 ///
@@ -97,22 +145,67 @@ pub type ParseResult<T> = Result<T, SyntaxError>;
 /// ## Example
 ///
 /// ```rust
-/// let mut lexer = Lexer::new("1+2");
-/// let tokens = lexer.lex()?;
+/// use parser::{eval::Evaluator, lexer::Lexer, parser::Parser};
+///
+/// let mut lexer = Lexer::new("1+2;");
+/// let tokens = lexer.lex().unwrap();
 ///
-/// let program = Parser::new(tokens).parse()?;
+/// let program = Parser::new(tokens).parse().unwrap();
 /// let mut evaluator = Evaluator::new();
 /// let v = evaluator.eval(&program);
-/// assert_eq!(v, 3);
+/// assert_eq!(v, Ok(3));
 /// ```
+///
+/// ## 改行モード
+///
+/// [`Parser::with_newlines`]で作ったパーサーは、`;`に加えて改行と入力の終端も文の終端とする。
+/// 改行トークンは[`Lexer::with_newlines`](crate::lexer::Lexer::with_newlines)が出力する。
+///
+/// ```text
+/// Term    -> ";" | Newline | EOF | (直後の"}")
+/// ```
+///
+/// 文の前やブロックの中の空行、`}`と`else`の間の改行は読み飛ばす。
+/// 式の途中での改行(`1 +\n2`など)はサポートしない。
 pub struct Parser {
     src: Peekable<std::vec::IntoIter<Token>>,
+    /// `true`の場合、改行と入力の終端も文の終端とする
+    newlines: bool,
+    /// 表にない演算子は[`BinaryOp::op_info`]に従う
+    table: PrecedenceTable,
+    /// パース中の文を囲むループの数。`break`がループの中にあるか検査するために使う
+    loop_depth: usize,
+    /// 現在位置を囲む`{`の数。エラーが起きた場合は減らさずに残し、
+    /// [`Parser::synchronize`]がエラー箇所を囲むブロックの終わりまで読み飛ばすために使う
+    block_depth: usize,
 }
 
 impl Parser {
     pub fn new(src: Vec<Token>) -> Self {
         Self {
             src: src.into_iter().peekable(),
+            newlines: false,
+            table: PrecedenceTable::new(),
+            loop_depth: 0,
+            block_depth: 0,
+        }
+    }
+
+    /// 二項演算子の優先度と結合順序を`table`で上書きしたパーサーを作る。
+    ///
+    /// 単項`-`の優先度は[`prec::UNARY`]のまま変わらない。
+    pub fn with_table(src: Vec<Token>, table: PrecedenceTable) -> Self {
+        Self {
+            table,
+            ..Self::new(src)
+        }
+    }
+
+    /// 改行で文を終えられるパーサーを作る。
+    pub fn with_newlines(src: Vec<Token>) -> Self {
+        Self {
+            newlines: true,
+            ..Self::new(src)
         }
     }
 
@@ -121,6 +214,65 @@ impl Parser {
         self.program()
     }
 
+    /// 式を1つだけパースする。REPLや数式の入力欄など、文ではなく式を受け付ける用途に使う。
+    ///
+    /// 式の後にトークンが残っている場合(`1 + 2;`の`;`など)はエラーを返す。
+    pub fn parse_expr(&mut self) -> ParseResult<Expression> {
+        Ok(self.parse_expr_spanned()?.0)
+    }
+
+    /// [`Parser::parse_expr`]と同様にパースし、式全体のソース上の範囲とともに返す。
+    pub fn parse_expr_spanned(&mut self) -> ParseResult<(Expression, Span)> {
+        let expr = self.spanned_expr(prec::LOWEST)?;
+        match self.src.next() {
+            Some(tok) => Err(SyntaxError::UnexpectedToken(tok)),
+            None => Ok(expr),
+        }
+    }
+
+    /// 構文エラーがあってもパースを続け、見つかった全てのエラーを返す。
+    ///
+    /// エラーが起きた文は読み飛ばし、次の`;`の直後から再開する(panic-mode recovery)。
+    /// ブロックの中でエラーが起きた場合は、そのブロックを閉じる`}`の直後から再開する。
+    /// 返却する[`Program`]にはエラーなくパースできた文のみが含まれる。
+    pub fn parse_recover(&mut self) -> (Program, Vec<SyntaxError>) {
+        let mut body = vec![];
+        let mut errors = vec![];
+
+        if self.is_eof() {
+            errors.push(SyntaxError::UnexpectedEof);
+        }
+        while !self.is_eof() {
+            match self.stmt() {
+                Ok(stmt) => body.push(stmt),
+                Err(e) => {
+                    errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+
+        (Program { body }, errors)
+    }
+
+    /// 次の文の先頭までトークンを読み飛ばす。
+    ///
+    /// エラーの原因となったトークンは消費されずに残っているため、ここから読み飛ばす。
+    /// トップレベルでは`;`または改行まで、ブロックの中ではエラー箇所を囲む全てのブロックを閉じる`}`まで読み飛ばし、
+    /// その記号自体も消費する。入力の終端に達した場合はそこで止まる。
+    fn synchronize(&mut self) {
+        let mut depth = std::mem::take(&mut self.block_depth);
+        for tok in self.src.by_ref() {
+            match tok.kind {
+                TokenKind::Semicolon | TokenKind::Newline if depth == 0 => break,
+                TokenKind::LeftBlock => depth += 1,
+                TokenKind::RightBlock if depth <= 1 => break,
+                TokenKind::RightBlock => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
     fn program(&mut self) -> ParseResult<Program> {
         let mut body = vec![];
 
@@ -133,40 +285,52 @@ impl Parser {
     }
 
     fn stmt(&mut self) -> ParseResult<Statement> {
+        self.skip_newlines();
         let tok = self.src.peek().ok_or(SyntaxError::UnexpectedEof)?;
 
         match tok.kind {
             TokenKind::If => Ok(self.r#if()?),
             TokenKind::While => Ok(self.r#while()?),
             TokenKind::For => Ok(self.r#for()?),
+            TokenKind::Break => Ok(self.r#break()?),
             TokenKind::LeftBlock => Ok(self.block_statement()?),
+            TokenKind::Semicolon => {
+                self.src.next();
+                Ok(Statement::Empty)
+            }
             _ => {
                 let expr = self.expr(prec::LOWEST)?;
-                self.expect(TokenKind::Semicolon)?;
+                self.terminator()?;
                 Ok(Statement::ExpressionStatement(expr))
             }
         }
     }
 
     fn r#if(&mut self) -> ParseResult<Statement> {
-        // If      -> "if" "(" E ")" "{" { Stmt } "}"
+        // If      -> "if" "(" E ")" "{" { Stmt } "}" [ Else ]
         self.src.next();
         self.expect(TokenKind::LeftParen)?;
         let cond = self.expr(prec::LOWEST)?;
         self.expect(TokenKind::RightParen)?;
 
-        self.expect(TokenKind::LeftBlock)?;
+        let then = self.block()?;
+        self.skip_newlines();
 
-        let mut then = vec![];
-        while let Some(tok) = self.src.peek()
-            && tok.kind != TokenKind::RightBlock
-        {
-            then.push(self.stmt()?);
-        }
-
-        self.expect(TokenKind::RightBlock)?;
+        // Else    -> "else" ( If | "{" { Stmt } "}" )
+        // 本体は必ず`{ }`で囲まれるため、`else`は直前の閉じた`}`を持つ`if`に対応する。
+        // そのため、いわゆるdangling elseの曖昧さは生じない。
+        let r#else = match self.src.peek() {
+            Some(tok) if tok.kind == TokenKind::Else => {
+                self.src.next();
+                match self.src.peek() {
+                    Some(tok) if tok.kind == TokenKind::If => Some(vec![self.r#if()?]),
+                    _ => Some(self.block()?),
+                }
+            }
+            _ => None,
+        };
 
-        Ok(Statement::If(If { cond, then }))
+        Ok(Statement::If(If { cond, then, r#else }))
     }
 
     fn r#while(&mut self) -> ParseResult<Statement> {
@@ -176,16 +340,7 @@ impl Parser {
         let cond = self.expr(prec::LOWEST)?;
         self.expect(TokenKind::RightParen)?;
 
-        self.expect(TokenKind::LeftBlock)?;
-
-        let mut body = vec![];
-        while let Some(tok) = self.src.peek()
-            && tok.kind != TokenKind::RightBlock
-        {
-            body.push(self.stmt()?);
-        }
-
-        self.expect(TokenKind::RightBlock)?;
+        let body = self.loop_body()?;
 
         Ok(Statement::While(While { cond, body }))
     }
@@ -213,16 +368,7 @@ impl Parser {
         };
 
         self.expect(TokenKind::RightParen)?;
-        self.expect(TokenKind::LeftBlock)?;
-
-        let mut body = vec![];
-        while let Some(tok) = self.src.peek()
-            && tok.kind != TokenKind::RightBlock
-        {
-            body.push(self.stmt()?);
-        }
-
-        self.expect(TokenKind::RightBlock)?;
+        let body = self.loop_body()?;
 
         Ok(Statement::For(For {
             init,
@@ -232,32 +378,69 @@ impl Parser {
         }))
     }
 
+    fn r#break(&mut self) -> ParseResult<Statement> {
+        // Break   -> "break" Term
+        let tok = self.src.next().ok_or(SyntaxError::UnexpectedEof)?;
+        if self.loop_depth == 0 {
+            return Err(SyntaxError::UnexpectedToken(tok));
+        }
+        self.terminator()?;
+
+        Ok(Statement::Break)
+    }
+
+    /// ループの本体をパースする。本体の中では`break`を書ける。
+    fn loop_body(&mut self) -> ParseResult<Vec<Statement>> {
+        self.loop_depth += 1;
+        let body = self.block();
+        self.loop_depth -= 1;
+        body
+    }
+
     fn block_statement(&mut self) -> ParseResult<Statement> {
+        Ok(Statement::BlockStatement(self.block()?))
+    }
+
+    /// `"{" { Stmt } "}"`をパースし、中の文を返す。
+    fn block(&mut self) -> ParseResult<Vec<Statement>> {
+        self.skip_newlines();
         self.expect(TokenKind::LeftBlock)?;
+        self.block_depth += 1;
 
         let mut body = vec![];
-        while let Some(tok) = self.src.peek()
-            && tok.kind != TokenKind::RightBlock
-        {
-            body.push(self.stmt()?);
+        loop {
+            self.skip_newlines();
+            match self.src.peek() {
+                Some(tok) if tok.kind != TokenKind::RightBlock => body.push(self.stmt()?),
+                _ => break,
+            }
         }
 
         self.expect(TokenKind::RightBlock)?;
+        self.block_depth -= 1;
 
-        Ok(Statement::BlockStatement(body))
+        Ok(body)
     }
 
     fn expr(&mut self, min_prec: u8) -> ParseResult<Expression> {
+        Ok(self.spanned_expr(min_prec)?.0)
+    }
+
+    /// 式をパースし、式全体のソース上の範囲とともに返す。
+    ///
+    /// 範囲は式の最も左のトークンから最も右のトークンまでで、括弧も含む。
+    fn spanned_expr(&mut self, min_prec: u8) -> ParseResult<(Expression, Span)> {
         // Precedence climbing algorithmを使用してパースを行う。
         // see: https://www.engr.mun.ca/~theo/Misc/exp_parsing.htm#climbing
 
-        let mut lhs = self.primary()?;
+        let (mut lhs, mut span) = self.primary()?;
 
         while let Some(tok) = self.src.peek() {
             let Ok(op) = BinaryOp::try_from(&tok.kind) else {
                 break;
             };
-            let info = op.op_info();
+            // 表にない演算子は既定の優先度に従う
+            let info = self.table.get(&op).copied().unwrap_or_else(|| op.op_info());
 
             if !info.binds_at(min_prec) {
                 break;
@@ -279,54 +462,840 @@ impl Parser {
                 Assoc::Left => info.prec + 1,
                 Assoc::Right => info.prec,
             };
-            let rhs = self.expr(next_prec)?;
+            let (rhs, rhs_span) = self.spanned_expr(next_prec)?;
+            span = Span::merge(span, rhs_span);
             lhs = Expression::Binary {
                 lhs: Box::new(lhs),
                 op,
                 rhs: Box::new(rhs),
+                span: span.clone(),
             };
         }
 
-        Ok(lhs)
+        Ok((lhs, span))
     }
 
-    fn primary(&mut self) -> ParseResult<Expression> {
-        let tok = self.src.next().ok_or(SyntaxError::UnexpectedEof)?;
+    fn primary(&mut self) -> ParseResult<(Expression, Span)> {
+        // 式を始められないトークンは、エラーからの回復のために消費せずに残す
+        let tok = self
+            .src
+            .next_if(|tok| {
+                matches!(
+                    tok.kind,
+                    TokenKind::Num(_)
+                        | TokenKind::Minus
+                        | TokenKind::Increment
+                        | TokenKind::Decrement
+                        | TokenKind::LeftParen
+                        | TokenKind::LeftBlock
+                        | TokenKind::Ident(_)
+                )
+            })
+            .ok_or_else(|| match self.src.peek() {
+                Some(tok) => SyntaxError::ExpectedExpression(tok.clone()),
+                None => SyntaxError::UnexpectedEof,
+            })?;
+        let span = tok.span.clone();
 
         let primary = match tok.kind {
-            TokenKind::Num(n) => Expression::Value(n),
+            TokenKind::Num(n) => (Expression::Value(n), span),
             TokenKind::Minus => {
-                let expr = self.expr(prec::UNARY)?;
-                Expression::Unary {
+                let (expr, expr_span) = self.spanned_expr(prec::UNARY)?;
+                let expr = Expression::Unary {
                     op: UnaryOp::Minus,
                     expr: Box::new(expr),
-                }
+                };
+                (expr, Span::merge(span, expr_span))
+            }
+            TokenKind::Increment | TokenKind::Decrement => {
+                let op = if tok.kind == TokenKind::Increment {
+                    BinaryOp::Plus
+                } else {
+                    BinaryOp::Minus
+                };
+                let (target, target_span) = self.primary()?;
+                let Expression::Var(name) = target else {
+                    return Err(SyntaxError::InvalidAssignmentTarget(tok));
+                };
+                // x = x + 1 に展開する。展開した式はどちらも`++x`全体の範囲を持つ
+                let span = Span::merge(span, target_span);
+                let expr = Expression::Binary {
+                    lhs: Box::new(Expression::Var(name.clone())),
+                    op: BinaryOp::Assign,
+                    rhs: Box::new(Expression::Binary {
+                        lhs: Box::new(Expression::Var(name)),
+                        op,
+                        rhs: Box::new(Expression::Value(1)),
+                        span: span.clone(),
+                    }),
+                    span: span.clone(),
+                };
+                (expr, span)
             }
             TokenKind::LeftParen => {
                 let expr = self.expr(prec::LOWEST)?;
-                if self.expect(TokenKind::RightParen).is_err() {
+                let Ok(right) = self.expect(TokenKind::RightParen) else {
                     return Err(SyntaxError::UnmatchedLeftParen(tok));
-                }
-                expr
+                };
+                (expr, Span::merge(span, right.span))
+            }
+            TokenKind::LeftBlock => {
+                let (expr, right) = self.block_expr()?;
+                (expr, Span::merge(span, right))
+            }
+            TokenKind::Ident(_)
+                if self.src.peek().map(|t| &t.kind) == Some(&TokenKind::LeftParen) =>
+            {
+                self.call(tok)?
             }
-            TokenKind::Ident(name) => Expression::Var(name),
-            _ => return Err(SyntaxError::UnexpectedToken(tok)),
+            TokenKind::Ident(name) => (Expression::Var(name), span),
+            _ => unreachable!("only tokens that can start an expression are consumed"),
         };
 
         Ok(primary)
     }
 
-    /// 次のトークンが期待した`TokenKind`であることを確認し、消費する。
-    /// 異なる種類、またはEoFの場合はエラーを返す。
-    fn expect(&mut self, expected: TokenKind) -> Result<(), SyntaxError> {
-        match self.src.next() {
-            Some(tok) if tok.kind == expected => Ok(()),
-            Some(tok) => Err(SyntaxError::UnexpectedToken(tok)),
+    /// 関数名`tok`の直後から`"(" [ E { "," E } ] ")"`をパースし、関数名から`)`までの範囲とともに返す。
+    fn call(&mut self, tok: Token) -> ParseResult<(Expression, Span)> {
+        let TokenKind::Ident(name) = &tok.kind else {
+            unreachable!("function name must be an identifier");
+        };
+        let Some(func) = Builtin::from_name(name) else {
+            return Err(SyntaxError::UnknownFunction(tok));
+        };
+        self.expect(TokenKind::LeftParen)?;
+
+        let mut args = vec![];
+        let right = match self.src.next_if(|t| t.kind == TokenKind::RightParen) {
+            Some(right) => right,
+            None => {
+                loop {
+                    args.push(self.expr(prec::LOWEST)?);
+                    if self.src.next_if(|t| t.kind == TokenKind::Comma).is_none() {
+                        break;
+                    }
+                }
+                self.expect(TokenKind::RightParen)?
+            }
+        };
+
+        if args.len() != func.arity() {
+            return Err(SyntaxError::WrongArity {
+                tok,
+                func,
+                found: args.len(),
+            });
+        }
+        let span = Span::merge(tok.span, right.span);
+        Ok((Expression::Call { func, args }, span))
+    }
+
+    /// `"{"`の直後から`{ Stmt } [ E ] "}"`をパースする。
+    ///
+    /// 末尾の`;`のない式をブロックの値とする。
+    /// 文の位置に書かれた`{`は[`Statement::BlockStatement`]としてパースするため、
+    /// ブロック式になるのは`y = { 1; 2 };`のように式の位置に書かれた場合のみ。
+    ///
+    /// 閉じる`}`の範囲もあわせて返す。
+    fn block_expr(&mut self) -> ParseResult<(Expression, Span)> {
+        // 式の評価の途中でループを抜けないよう、ブロック式の中では`break`を禁止する
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let block = self.block_expr_body();
+        self.loop_depth = loop_depth;
+        block
+    }
+
+    fn block_expr_body(&mut self) -> ParseResult<(Expression, Span)> {
+        self.block_depth += 1;
+        let mut body = vec![];
+        let mut value = None;
+
+        loop {
+            self.skip_newlines();
+            let tok = self.src.peek().ok_or(SyntaxError::UnexpectedEof)?;
+            match tok.kind {
+                TokenKind::RightBlock => break,
+                TokenKind::If
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Break
+                | TokenKind::LeftBlock => body.push(self.stmt()?),
+                _ => {
+                    let expr = self.expr(prec::LOWEST)?;
+                    // 改行モードでは、`}`の直前の改行を挟んでもブロックの値とする
+                    let newline = self.skip_newlines();
+                    match self.src.peek() {
+                        Some(tok) if tok.kind == TokenKind::RightBlock => {
+                            value = Some(Box::new(expr));
+                            break;
+                        }
+                        _ if newline && self.newlines => {
+                            body.push(Statement::ExpressionStatement(expr));
+                        }
+                        _ => {
+                            self.terminator()?;
+                            body.push(Statement::ExpressionStatement(expr));
+                        }
+                    }
+                }
+            }
+        }
+        let right = self.expect(TokenKind::RightBlock)?;
+        self.block_depth -= 1;
+
+        Ok((Expression::Block { body, value }, right.span))
+    }
+
+    /// 次のトークンが期待した`TokenKind`であることを確認し、消費したトークンを返す。
+    /// 異なる種類、またはEoFの場合はエラーを返す。異なる種類のトークンは消費しない。
+    fn expect(&mut self, expected: TokenKind) -> ParseResult<Token> {
+        if let Some(tok) = self.src.next_if(|tok| tok.kind == expected) {
+            return Ok(tok);
+        }
+        match self.src.peek() {
+            Some(tok) => Err(SyntaxError::UnexpectedToken(tok.clone())),
             None => Err(SyntaxError::UnexpectedEof),
         }
     }
 
+    /// 文の終端を読む。改行モードでは`;`に加えて改行と入力の終端も受け付ける。
+    fn terminator(&mut self) -> ParseResult<()> {
+        if self.newlines {
+            match self.src.peek() {
+                // `if (x) { y = 1 }`のように、`}`の直前の文は終端を省略できる
+                None => return Ok(()),
+                Some(tok) if tok.kind == TokenKind::RightBlock => return Ok(()),
+                Some(tok) if tok.kind == TokenKind::Newline => {
+                    self.src.next();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        self.expect(TokenKind::Semicolon)?;
+        Ok(())
+    }
+
+    /// 連続する改行を読み飛ばし、1つ以上読み飛ばしたかを返す。
+    /// 改行モードでなければ改行トークンは現れないため、何もしない。
+    fn skip_newlines(&mut self) -> bool {
+        let mut skipped = false;
+        while self
+            .src
+            .next_if(|tok| tok.kind == TokenKind::Newline)
+            .is_some()
+        {
+            skipped = true;
+        }
+        skipped
+    }
+
+    /// 改行のみが残っている場合も終端とみなす。
     fn is_eof(&mut self) -> bool {
+        self.skip_newlines();
         self.src.peek().is_none()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, tok};
+
+    fn parse_expr(input: &str) -> Expression {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let mut program = Parser::new(tokens).parse().unwrap();
+        match program.body.remove(0) {
+            Statement::ExpressionStatement(expr) => expr,
+            stmt => panic!("expected an expression statement, got {:?}", stmt),
+        }
+    }
+
+    fn num(n: i32) -> Expression {
+        Expression::Value(n)
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(name.to_string())
+    }
+
+    fn neg(expr: Expression) -> Expression {
+        Expression::Unary {
+            op: UnaryOp::Minus,
+            expr: Box::new(expr),
+        }
+    }
+
+    fn bin(lhs: Expression, op: BinaryOp, rhs: Expression) -> Expression {
+        Expression::Binary {
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+            span: Span::default(),
+        }
+    }
+
+    fn parse_recover(input: &str) -> (Program, Vec<SyntaxError>) {
+        let tokens = Lexer::new(input).lex().unwrap();
+        Parser::new(tokens).parse_recover()
+    }
+
+    #[test]
+    fn recover_reports_all_errors() {
+        let (program, errors) = parse_recover("1 + * 2; x = 1; 3 + ) 4;");
+
+        assert_eq!(
+            errors,
+            [
+                SyntaxError::ExpectedExpression(tok!(TokenKind::Mul, 4, 5)),
+                SyntaxError::ExpectedExpression(tok!(TokenKind::RightParen, 20, 21)),
+            ]
+        );
+        assert_eq!(program.body.len(), 1);
+    }
+
+    #[test]
+    fn recover_without_errors() {
+        let (program, errors) = parse_recover("x = 1; x;");
+
+        assert!(errors.is_empty());
+        assert_eq!(program.body.len(), 2);
+    }
+
+    #[test]
+    fn mixed_associativity() {
+        // a = (b + (c ^ (d ^ e)))
+        let expected = bin(
+            var("a"),
+            BinaryOp::Assign,
+            bin(
+                var("b"),
+                BinaryOp::Plus,
+                bin(
+                    var("c"),
+                    BinaryOp::Pow,
+                    bin(var("d"), BinaryOp::Pow, var("e")),
+                ),
+            ),
+        );
+        assert_eq!(parse_expr("a = b + c ^ d ^ e;"), expected);
+    }
+
+    #[test]
+    fn left_associative_chain() {
+        // ((1 - 2) - 3) - 4
+        let expected = bin(
+            bin(
+                bin(num(1), BinaryOp::Minus, num(2)),
+                BinaryOp::Minus,
+                num(3),
+            ),
+            BinaryOp::Minus,
+            num(4),
+        );
+        assert_eq!(parse_expr("1 - 2 - 3 - 4;"), expected);
+    }
+
+    #[test]
+    fn chained_assignment_with_comparison() {
+        // a = (b = ((1 + (2 * 3)) < 4))
+        let expected = bin(
+            var("a"),
+            BinaryOp::Assign,
+            bin(
+                var("b"),
+                BinaryOp::Assign,
+                bin(
+                    bin(num(1), BinaryOp::Plus, bin(num(2), BinaryOp::Mul, num(3))),
+                    BinaryOp::Lt,
+                    num(4),
+                ),
+            ),
+        );
+        assert_eq!(parse_expr("a = b = 1 + 2 * 3 < 4;"), expected);
+    }
+
+    #[test]
+    fn pow_binds_tighter_than_unary_and_mul() {
+        // (-(2 ^ 2)) * (3 ^ 2)
+        let expected = bin(
+            neg(bin(num(2), BinaryOp::Pow, num(2))),
+            BinaryOp::Mul,
+            bin(num(3), BinaryOp::Pow, num(2)),
+        );
+        assert_eq!(parse_expr("-2 ^ 2 * 3 ^ 2;"), expected);
+    }
+
+    #[test]
+    fn left_associative_after_right_associative() {
+        // ((2 ^ (3 ^ 2)) / 4) / 2
+        let expected = bin(
+            bin(
+                bin(num(2), BinaryOp::Pow, bin(num(3), BinaryOp::Pow, num(2))),
+                BinaryOp::Div,
+                num(4),
+            ),
+            BinaryOp::Div,
+            num(2),
+        );
+        assert_eq!(parse_expr("2 ^ 3 ^ 2 / 4 / 2;"), expected);
+    }
+
+    #[test]
+    fn block_expression() {
+        assert_eq!(
+            parse_expr("y = { 1; x = 2; x + 1 };"),
+            bin(
+                var("y"),
+                BinaryOp::Assign,
+                Expression::Block {
+                    body: vec![
+                        Statement::ExpressionStatement(num(1)),
+                        Statement::ExpressionStatement(bin(var("x"), BinaryOp::Assign, num(2))),
+                    ],
+                    value: Some(Box::new(bin(var("x"), BinaryOp::Plus, num(1)))),
+                }
+            )
+        );
+        assert_eq!(
+            parse_expr("-{ 1; };"),
+            neg(Expression::Block {
+                body: vec![Statement::ExpressionStatement(num(1))],
+                value: None,
+            })
+        );
+    }
+
+    #[test]
+    fn block_in_statement_position_is_a_statement() {
+        let tokens = Lexer::new("{ 1; }").lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program.body,
+            [Statement::BlockStatement(vec![
+                Statement::ExpressionStatement(num(1))
+            ])]
+        );
+
+        // 文としてのブロックでは、末尾の式にも`;`が必要
+        let tokens = Lexer::new("{ 1 }").lex().unwrap();
+        assert!(matches!(
+            Parser::new(tokens).parse(),
+            Err(SyntaxError::UnexpectedToken(_))
+        ));
+    }
+
+    #[test]
+    fn empty_statements() {
+        let parse = |input: &str| Parser::new(Lexer::new(input).lex().unwrap()).parse();
+
+        assert_eq!(parse(";").unwrap().body, [Statement::Empty]);
+        assert_eq!(
+            parse(";;;").unwrap().body,
+            [Statement::Empty, Statement::Empty, Statement::Empty]
+        );
+        assert_eq!(
+            parse("{x=1;};").unwrap().body,
+            [
+                Statement::BlockStatement(vec![Statement::ExpressionStatement(
+                    Expression::Binary {
+                        lhs: Box::new(Expression::Var("x".to_string())),
+                        op: BinaryOp::Assign,
+                        rhs: Box::new(num(1)),
+                        span: Span::default(),
+                    }
+                )]),
+                Statement::Empty,
+            ]
+        );
+        assert_eq!(
+            parse("if (1) { ; }").unwrap().body[0].to_string(),
+            "if (1) { ; }"
+        );
+    }
+
+    #[test]
+    fn parse_single_expression() {
+        let parse = |input: &str| Parser::new(Lexer::new(input).lex().unwrap()).parse_expr();
+
+        assert_eq!(
+            parse("1+2*3"),
+            Ok(bin(
+                num(1),
+                BinaryOp::Plus,
+                bin(num(2), BinaryOp::Mul, num(3))
+            ))
+        );
+        assert_eq!(
+            parse("1+2;"),
+            Err(SyntaxError::UnexpectedToken(tok!(
+                TokenKind::Semicolon,
+                3,
+                4
+            )))
+        );
+        assert_eq!(parse(""), Err(SyntaxError::UnexpectedEof));
+    }
+
+    fn parse_newlines(input: &str) -> ParseResult<Program> {
+        let tokens = Lexer::with_newlines(input).lex().unwrap();
+        Parser::with_newlines(tokens).parse()
+    }
+
+    #[test]
+    fn newline_terminated_statements() {
+        let program = parse_newlines("x = 1\nx + 1\n").unwrap();
+        assert_eq!(program.body.len(), 2);
+        assert_eq!(
+            program.body,
+            Parser::new(Lexer::new("x = 1; x + 1;").lex().unwrap())
+                .parse()
+                .unwrap()
+                .body
+        );
+
+        // 末尾の改行の省略、空行、`;`との混在
+        let program = parse_newlines("\n\nx = 1; y = 2\n\n\nx + y").unwrap();
+        assert_eq!(program.body.len(), 3);
+    }
+
+    #[test]
+    fn newline_terminated_blocks() {
+        let input =
+            "if (x) {\n  y = 1\n}\nelse {\n  y = 2\n}\nz = { y\n  y * 2\n}\nwhile (0) { y = 3 }\n";
+        let program = parse_newlines(input).unwrap();
+        assert_eq!(
+            program.body,
+            Parser::new(
+                Lexer::new(
+                    "if (x) { y = 1; } else { y = 2; } z = { y; y * 2 }; while (0) { y = 3; }"
+                )
+                .lex()
+                .unwrap()
+            )
+            .parse()
+            .unwrap()
+            .body
+        );
+    }
+
+    #[test]
+    fn newline_mode_is_opt_in() {
+        // 通常のレキサーは改行を読み飛ばすため、`;`が必要
+        let tokens = Lexer::new("x = 1\nx + 1").lex().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+
+        // 通常のパーサーは改行を文の終端として受け付けない
+        let tokens = Lexer::with_newlines("x = 1\nx + 1\n").lex().unwrap();
+        assert!(matches!(
+            Parser::new(tokens).parse(),
+            Err(SyntaxError::UnexpectedToken(Token {
+                kind: TokenKind::Newline,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn custom_precedence_table() {
+        let parse_with = |table: PrecedenceTable| {
+            let tokens = Lexer::new("1+2*3-4;").lex().unwrap();
+            let mut program = Parser::with_table(tokens, table).parse().unwrap();
+            program.body.remove(0).to_string()
+        };
+
+        assert_eq!(parse_with(PrecedenceTable::new()), "((1 + (2 * 3)) - 4);");
+
+        // `+`と`*`の優先度を入れ替える。`-`は既定の優先度のままなので`*`と同じ強さになる
+        let swapped = PrecedenceTable::from([
+            (BinaryOp::Plus, BinaryOp::Mul.op_info()),
+            (BinaryOp::Mul, BinaryOp::Plus.op_info()),
+        ]);
+        assert_eq!(parse_with(swapped), "(((1 + 2) * 3) - 4);");
+
+        // `+`を右結合にする
+        let right = PrecedenceTable::from([(
+            BinaryOp::Plus,
+            OpInfo {
+                prec: prec::PLUS,
+                assoc: Assoc::Right,
+            },
+        )]);
+        let tokens = Lexer::new("1+2+3;").lex().unwrap();
+        let program = Parser::with_table(tokens, right).parse().unwrap();
+        assert_eq!(program.body[0].to_string(), "(1 + (2 + 3));");
+    }
+
+    #[test]
+    fn break_only_inside_loops() {
+        let parse = |input: &str| Parser::new(Lexer::new(input).lex().unwrap()).parse();
+
+        assert!(parse("while (1) { if (x) { break; } }").is_ok());
+        assert!(parse("for (;;) { { break; } }").is_ok());
+        assert!(matches!(
+            parse("break;"),
+            Err(SyntaxError::UnexpectedToken(Token {
+                kind: TokenKind::Break,
+                ..
+            }))
+        ));
+        assert!(parse("if (1) { break; }").is_err());
+        // ブロック式の中ではループの中でも書けない
+        assert!(parse("while (1) { x = { break; 1 }; }").is_err());
+        assert!(parse("while (1) { x = { while (1) { break; } 1 }; }").is_ok());
+    }
+
+    #[test]
+    fn expected_expression() {
+        let parse_err = |input: &str| {
+            Parser::new(Lexer::new(input).lex().unwrap())
+                .parse()
+                .unwrap_err()
+        };
+
+        assert_eq!(
+            parse_err("1 + ;"),
+            SyntaxError::ExpectedExpression(tok!(TokenKind::Semicolon, 4, 5))
+        );
+        assert_eq!(
+            parse_err("( );"),
+            SyntaxError::ExpectedExpression(tok!(TokenKind::RightParen, 2, 3))
+        );
+        assert_eq!(
+            parse_err("}"),
+            SyntaxError::ExpectedExpression(tok!(TokenKind::RightBlock, 0, 1))
+        );
+        // 式の後に余分なトークンがある場合は従来どおり
+        assert_eq!(
+            parse_err("1 2;"),
+            SyntaxError::UnexpectedToken(tok!(TokenKind::Num(2), 2, 3))
+        );
+        assert_eq!(
+            SyntaxError::ExpectedExpression(tok!(TokenKind::Semicolon, 4, 5)).to_string(),
+            "Expected an expression, found Semicolon"
+        );
+    }
+
+    #[test]
+    fn builtin_calls() {
+        let call = |func, args| Expression::Call { func, args };
+        assert_eq!(
+            parse_expr("abs(-5);"),
+            call(Builtin::Abs, vec![neg(num(5))])
+        );
+        assert_eq!(
+            parse_expr("max(x, 1 + 2) * 2;"),
+            bin(
+                call(
+                    Builtin::Max,
+                    vec![var("x"), bin(num(1), BinaryOp::Plus, num(2))]
+                ),
+                BinaryOp::Mul,
+                num(2)
+            )
+        );
+        // 直後に`(`がなければ変数として扱う
+        assert_eq!(
+            parse_expr("abs + 1;"),
+            bin(var("abs"), BinaryOp::Plus, num(1))
+        );
+    }
+
+    #[test]
+    fn builtin_call_errors() {
+        let parse_err = |input: &str| {
+            Parser::new(Lexer::new(input).lex().unwrap())
+                .parse()
+                .unwrap_err()
+        };
+
+        assert_eq!(
+            parse_err("min(1);"),
+            SyntaxError::WrongArity {
+                tok: tok!(TokenKind::Ident("min".to_string()), 0, 3),
+                func: Builtin::Min,
+                found: 1,
+            }
+        );
+        assert_eq!(
+            parse_err("abs();"),
+            SyntaxError::WrongArity {
+                tok: tok!(TokenKind::Ident("abs".to_string()), 0, 3),
+                func: Builtin::Abs,
+                found: 0,
+            }
+        );
+        assert_eq!(
+            parse_err("foo(1);"),
+            SyntaxError::UnknownFunction(tok!(TokenKind::Ident("foo".to_string()), 0, 3))
+        );
+        assert_eq!(
+            parse_err("max(1, 2;"),
+            SyntaxError::UnexpectedToken(tok!(TokenKind::Semicolon, 8, 9))
+        );
+        assert_eq!(
+            parse_err("1, 2;"),
+            SyntaxError::UnexpectedToken(tok!(TokenKind::Comma, 1, 2))
+        );
+        assert_eq!(
+            SyntaxError::WrongArity {
+                tok: tok!(TokenKind::Ident("min".to_string()), 0, 3),
+                func: Builtin::Min,
+                found: 1,
+            }
+            .to_string(),
+            "`min` takes 2 argument(s), but 1 given"
+        );
+    }
+
+    #[test]
+    fn expression_spans() {
+        fn span(input: &str) -> &str {
+            let (_, span) = Parser::new(Lexer::new(input).lex().unwrap())
+                .parse_expr_spanned()
+                .unwrap();
+            &input[span.start..span.end]
+        }
+
+        // 最も左のトークンから最も右のトークンまで
+        assert_eq!(span("  1 + 2 * x  "), "1 + 2 * x");
+        assert_eq!(span("a = b = 3"), "a = b = 3");
+        assert_eq!(span("-x ^ 2"), "-x ^ 2");
+        // 括弧・ブロック・呼び出しは閉じる記号まで含む
+        assert_eq!(span("(1 + 2) * (3)"), "(1 + 2) * (3)");
+        assert_eq!(span("{ x = 1; x } + 1"), "{ x = 1; x } + 1");
+        assert_eq!(span("2 * max(1, 3)"), "2 * max(1, 3)");
+    }
+
+    #[test]
+    fn binary_spans() {
+        let input = "(1 + 2) * x / ++y";
+        let expr = Parser::new(Lexer::new(input).lex().unwrap())
+            .parse_expr()
+            .unwrap();
+        let Expression::Binary { lhs, rhs, span, .. } = &expr else {
+            panic!("expected a binary expression: {expr:?}");
+        };
+        assert_eq!(&input[span.start..span.end], input);
+        let Expression::Binary { lhs, span, .. } = lhs.as_ref() else {
+            panic!("expected a binary expression: {lhs:?}");
+        };
+        assert_eq!(&input[span.start..span.end], "(1 + 2) * x");
+        // 括弧の中の式は括弧を含まない
+        let Expression::Binary { span, .. } = lhs.as_ref() else {
+            panic!("expected a binary expression: {lhs:?}");
+        };
+        assert_eq!(&input[span.start..span.end], "1 + 2");
+        // `++y`を展開した代入は`++y`全体の範囲を持つ
+        let Expression::Binary { span, .. } = rhs.as_ref() else {
+            panic!("expected a binary expression: {rhs:?}");
+        };
+        assert_eq!(&input[span.start..span.end], "++y");
+
+        // 範囲が違っても同じ構造の式は等しい
+        assert_eq!(expr, parse_expr("(1+2)*x/++y;"));
+    }
+
+    fn recovered_stmts(program: &Program) -> Vec<String> {
+        program.body.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn recover_at_semicolon() {
+        // エラーの原因の`;`で止まり、同じ行の次の文から再開する
+        let (program, errors) = parse_recover("a +; b;");
+        assert_eq!(
+            errors,
+            [SyntaxError::ExpectedExpression(tok!(
+                TokenKind::Semicolon,
+                3,
+                4
+            ))]
+        );
+        assert_eq!(recovered_stmts(&program), ["b;"]);
+
+        let (program, errors) = parse_recover("x = (1; b;");
+        assert_eq!(
+            errors,
+            [SyntaxError::UnmatchedLeftParen(tok!(
+                TokenKind::LeftParen,
+                4,
+                5
+            ))]
+        );
+        assert_eq!(recovered_stmts(&program), ["b;"]);
+    }
+
+    #[test]
+    fn recover_at_right_block() {
+        // ブロックの中のエラーは、ブロックを閉じる`}`まで読み飛ばす
+        let (program, errors) = parse_recover("if (1) { a +; c; } b;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(recovered_stmts(&program), ["b;"]);
+
+        let (program, errors) = parse_recover("while (1) { if (x) { y = ); } z; } b; c +;");
+        assert_eq!(
+            errors,
+            [
+                SyntaxError::ExpectedExpression(tok!(TokenKind::RightParen, 25, 26)),
+                SyntaxError::ExpectedExpression(tok!(TokenKind::Semicolon, 41, 42)),
+            ]
+        );
+        assert_eq!(recovered_stmts(&program), ["b;"]);
+    }
+
+    #[test]
+    fn recover_at_eof() {
+        let (program, errors) = parse_recover("a; b +");
+        assert_eq!(errors, [SyntaxError::UnexpectedEof]);
+        assert_eq!(recovered_stmts(&program), ["a;"]);
+
+        // 閉じられていないブロックも入力の終端で止まる
+        let (program, errors) = parse_recover("a; { b +; c;");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(recovered_stmts(&program), ["a;"]);
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        assert_eq!(
+            parse_expr("++x;"),
+            bin(
+                var("x"),
+                BinaryOp::Assign,
+                bin(var("x"), BinaryOp::Plus, num(1))
+            )
+        );
+        assert_eq!(
+            parse_expr("--x * 2;"),
+            bin(
+                bin(
+                    var("x"),
+                    BinaryOp::Assign,
+                    bin(var("x"), BinaryOp::Minus, num(1))
+                ),
+                BinaryOp::Mul,
+                num(2)
+            )
+        );
+
+        let parse_err = |input: &str| {
+            Parser::new(Lexer::new(input).lex().unwrap())
+                .parse()
+                .unwrap_err()
+        };
+        assert_eq!(
+            parse_err("++5;"),
+            SyntaxError::InvalidAssignmentTarget(tok!(TokenKind::Increment, 0, 2))
+        );
+        assert_eq!(
+            parse_err("--5;"),
+            SyntaxError::InvalidAssignmentTarget(tok!(TokenKind::Decrement, 0, 2))
+        );
+        // 後置はサポートしない
+        assert_eq!(
+            parse_err("x++;"),
+            SyntaxError::UnexpectedToken(tok!(TokenKind::Increment, 1, 3))
+        );
+    }
+}
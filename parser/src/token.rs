@@ -1,21 +1,35 @@
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
 }
 
+impl Span {
+    /// `a`と`b`の両方を含む最小の範囲を返す。間にある文字も範囲に含む。
+    pub fn merge(a: Span, b: Span) -> Span {
+        Span {
+            start: a.start.min(b.start),
+            end: a.end.max(b.end),
+        }
+    }
+}
+
 pub trait Spanned {
     fn span(&self) -> Option<Span>;
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum TokenKind {
     Plus,
     Minus,
     Mul,
     Div,
+    Percent, // %
     Pow,
     Assign,
+    Bang,      // !
+    Increment, // ++
+    Decrement, // --
 
     Eq,   // ==
     Neq,  // !=
@@ -25,8 +39,10 @@ pub enum TokenKind {
     LtEq, // <=
 
     If,
+    Else,
     While,
     For,
+    Break,
 
     Num(i32),
     Ident(String),
@@ -37,6 +53,9 @@ pub enum TokenKind {
     RightBlock, // }
 
     Semicolon,
+    Comma, // ,
+    /// 改行。[`Lexer::with_newlines`](crate::lexer::Lexer::with_newlines)で作ったレキサーのみが出力する
+    Newline,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -57,3 +76,22 @@ macro_rules! tok {
         }
     }};
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn merge() {
+        assert_eq!(Span::merge(span(0, 1), span(4, 5)), span(0, 5));
+        // 順序によらない
+        assert_eq!(Span::merge(span(4, 5), span(0, 1)), span(0, 5));
+        // 一方が他方を含む場合
+        assert_eq!(Span::merge(span(2, 8), span(3, 4)), span(2, 8));
+        assert_eq!(Span::merge(span(3, 3), span(3, 3)), span(3, 3));
+    }
+}
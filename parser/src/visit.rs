@@ -0,0 +1,149 @@
+use crate::ast::{Expression, For, If, Program, Statement, While};
+
+/// ASTを走査するためのトレイト。
+///
+/// 各メソッドのデフォルト実装は子ノードを順に訪問する。
+/// 独自の処理が必要なノードのメソッドだけを上書きし、
+/// 子ノードも訪問したい場合は対応する`walk_*`関数を呼び出す。
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Statement) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &Expression) {
+        walk_expr(self, expr);
+    }
+}
+
+/// プログラム中に現れる変数名を、重複を除いて辞書順に返す。
+pub fn variables(program: &Program) -> Vec<String> {
+    #[derive(Default)]
+    struct VarCollector {
+        names: Vec<String>,
+    }
+
+    impl Visitor for VarCollector {
+        fn visit_expr(&mut self, expr: &Expression) {
+            if let Expression::Var(name) = expr {
+                self.names.push(name.clone());
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    let mut collector = VarCollector::default();
+    walk_program(&mut collector, program);
+
+    let mut names = collector.names;
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in &program.body {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Statement) {
+    match stmt {
+        Statement::ExpressionStatement(expr) => visitor.visit_expr(expr),
+        Statement::BlockStatement(body) => {
+            for s in body {
+                visitor.visit_stmt(s);
+            }
+        }
+        Statement::If(If { cond, then, r#else }) => {
+            visitor.visit_expr(cond);
+            for s in then.iter().chain(r#else.iter().flatten()) {
+                visitor.visit_stmt(s);
+            }
+        }
+        Statement::While(While { cond, body }) => {
+            visitor.visit_expr(cond);
+            for s in body {
+                visitor.visit_stmt(s);
+            }
+        }
+        Statement::For(For {
+            init,
+            cond,
+            update,
+            body,
+        }) => {
+            for e in [init, cond, update].into_iter().flatten() {
+                visitor.visit_expr(e);
+            }
+            for s in body {
+                visitor.visit_stmt(s);
+            }
+        }
+        Statement::Break | Statement::Empty => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Unary { expr, .. } => visitor.visit_expr(expr),
+        Expression::Binary { lhs, rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expression::Value(_) | Expression::Var(_) => {}
+        Expression::Call { args, .. } => {
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        Expression::Block { body, value } => {
+            for s in body {
+                visitor.visit_stmt(s);
+            }
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    #[derive(Default)]
+    struct BinaryCounter {
+        count: usize,
+    }
+
+    impl Visitor for BinaryCounter {
+        fn visit_expr(&mut self, expr: &Expression) {
+            if matches!(expr, Expression::Binary { .. }) {
+                self.count += 1;
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    fn count_binary(input: &str) -> usize {
+        let tokens = Lexer::new(input).lex().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let mut counter = BinaryCounter::default();
+        walk_program(&mut counter, &program);
+        counter.count
+    }
+
+    #[test]
+    fn count_binary_nodes() {
+        assert_eq!(count_binary("1+2*3;"), 2);
+    }
+
+    #[test]
+    fn count_binary_nodes_in_statements() {
+        let input = "for (i=0; i<10; i=i+1) { if (i==3) { x = -i; } else { { y = 1; } } }";
+        // i=0, i<10, i+1, i=..., i==3, x=-i, y=1
+        assert_eq!(count_binary(input), 7);
+    }
+}
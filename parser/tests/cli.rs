@@ -0,0 +1,195 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn parser(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_parser"))
+        .args(args)
+        .output()
+        .expect("failed to execute parser")
+}
+
+#[test]
+fn check_valid_program() {
+    let output = parser(&["--check", "x = 1; if (x) { x = 2; } x;"]);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn check_reports_every_syntax_error() {
+    let output = parser(&["--check", "1 + * 2; 3 + ) 4;"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Expected an expression, found Mul"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Expected an expression, found RightParen"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn check_reads_multiline_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_parser"))
+        .arg("--check")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to execute parser");
+
+    // 2行目にエラーがあるため、複数行全体が読み込まれていればエラーになる
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"x = 1;\nx + ;\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Expected an expression, found Semicolon"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn assignment_in_condition_is_a_warning() {
+    let output = parser(&["--check", "if (x = 0) {}"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(output.status.success());
+    assert!(stderr.starts_with("Warning: "), "{}", stderr);
+}
+
+#[test]
+fn assignment_in_condition_is_an_error_under_strict() {
+    let output = parser(&["--check", "--strict", "if (x = 0) {}"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("Lint error: "), "{}", stderr);
+}
+
+#[test]
+fn emit_eval_with_radix() {
+    let output = parser(&["--emit=eval", "--radix=hex", "255;"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0xff\n");
+}
+
+#[test]
+fn emit_eval_prints_booleans() {
+    let output = parser(&["--emit=eval", "--radix=hex", "1 < 2;"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "true\n");
+
+    let output = parser(&["--emit=eval", "3 + 4;"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7\n");
+}
+
+#[test]
+fn emit_cfg() {
+    let output = parser(&["--emit=cfg", "if (x) { 1; } else { 2; }"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.starts_with("digraph cfg {"), "{}", stdout);
+    assert!(stdout.contains("b0 -> b4 [label=\"true\"];"), "{}", stdout);
+    assert!(stdout.contains("b2 -> b1;"), "{}", stdout);
+}
+
+#[test]
+fn emit_eval_grouped() {
+    let output = parser(&["--emit=eval", "--grouped", "1000 * 1000;"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1,000,000\n");
+
+    let output = parser(&["--emit=eval", "1000 * 1000;"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1000000\n");
+}
+
+#[test]
+fn emit_eval_runtime_error() {
+    let output = parser(&["--emit=eval", "x = 0; 1 / x;"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("Runtime error: "), "{}", stderr);
+
+    // 除数が定数の0の場合は、評価の前に警告する
+    let output = parser(&["--emit=eval", "1 / 0;"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.starts_with("Warning: Division by zero\n1 / 0;\n^^^^^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn emit_eval_max_iterations() {
+    let output = parser(&["--emit=eval", "--max-iterations=1000", "while (1) {}"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("Runtime error: Loop iteration limit exceeded: 1000"),
+        "{}",
+        stderr
+    );
+
+    let output = parser(&[
+        "--emit=eval",
+        "--max-iterations=3",
+        "i = 0; while (i < 3) { i = i + 1; } i;",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn emit_eval_arith_mode() {
+    let input = "2147483647 + 1;";
+
+    let output = parser(&["--emit=eval", input]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.starts_with("Runtime error: "), "{}", stderr);
+
+    let output = parser(&["--emit=eval", "--arith=wrapping", input]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "-2147483648\n");
+}
+
+#[test]
+fn max_tokens_applies_to_every_mode() {
+    // `1 + 2;`は4トークン
+    for mode in ["--check", "--emit=eval", "--emit=cfg", "--emit=asm"] {
+        let output = parser(&[mode, "--max-tokens=3", "1 + 2;"]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+
+        assert!(!output.status.success(), "{}", mode);
+        assert!(
+            stderr.contains("Too many tokens: the limit is 3"),
+            "{}: {}",
+            mode,
+            stderr
+        );
+    }
+
+    let output = parser(&["--emit=eval", "--max-tokens=4", "1 + 2;"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
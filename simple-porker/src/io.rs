@@ -1,5 +1,5 @@
 use crate::Hands;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 
 pub enum DiscardAction {
     // スタンド（交換なし）
@@ -8,11 +8,12 @@ pub enum DiscardAction {
     Discard(Vec<usize>),
 }
 
-/// hands を表示し、ユーザーの入力に応じて Stand / Discard を返す
-pub fn prompt_discard(hands: &Hands) -> DiscardAction {
+/// hands を表示し、`input` から読んだ入力に応じて Stand / Discard を返す
+pub fn prompt_discard<R: BufRead>(hands: &Hands, input: &mut R) -> DiscardAction {
     println!("あなたの手札:\n{hands}");
 
     let input = prompt(
+        input,
         "交換したいカードの番号をスペース区切りで入力してください。\n交換しない場合は Enter を押してください。",
     );
 
@@ -38,8 +39,7 @@ pub fn prompt_discard(hands: &Hands) -> DiscardAction {
     }
 }
 
-fn prompt(ask: &str) -> String {
-    let stdin = io::stdin();
+fn prompt<R: BufRead>(reader: &mut R, ask: &str) -> String {
     let mut stdout = io::stdout();
 
     println!("{ask}");
@@ -48,7 +48,7 @@ fn prompt(ask: &str) -> String {
     stdout.flush().unwrap();
 
     let mut input = String::new();
-    if stdin.read_line(&mut input).is_err() {
+    if reader.read_line(&mut input).is_err() {
         eprintln!("入力エラー");
     }
 
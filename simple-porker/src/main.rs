@@ -1,32 +1,93 @@
+use rand::Rng;
 use rand::prelude::SliceRandom;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
+use std::io::BufRead;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 mod io;
 
 use crate::io::{DiscardAction, prompt_discard};
 
 fn main() {
+    // `--dealer` を指定すると、ディーラーの手札と勝負する
+    let vs_dealer = std::env::args().skip(1).any(|arg| arg == "--dealer");
     let mut deck = Deck::new();
-    let mut hands = Hands::new_from_deck(&mut deck);
+    play(&mut deck, &mut std::io::stdin().lock(), vs_dealer);
+}
+
+/// 1ゲームを遊ぶ。`input` からプレイヤーの入力を読む。
+///
+/// `vs_dealer` の場合は、プレイヤーの次にディーラーの手札を同じデッキから配り、
+/// 交換を終えたプレイヤーの手札と比べた結果を返す。ディーラーはカードを交換しない。
+fn play<R: BufRead>(deck: &mut Deck, input: &mut R, vs_dealer: bool) -> Option<Outcome> {
+    let mut hands = Hands::new_from_deck(deck);
+    let dealer = vs_dealer.then(|| Hands::new_from_deck(deck));
 
     for _ in 0..2 {
-        let action = prompt_discard(&hands);
+        let action = prompt_discard(&hands, input);
         match action {
             DiscardAction::Stand => break,
             DiscardAction::Discard(v) => {
-                for i in v {
-                    hands.exchange(&mut deck, hands[i]);
-                }
+                hands.exchange(deck, &v);
             }
         }
     }
 
     let rank = hands.rank();
     println!("{hands}");
-    println!("{rank:?}");
+    println!("{}", rank.describe(Locale::Ja));
+
+    let dealer = dealer?;
+    let outcome = Outcome::against(&hands, &dealer);
+    println!("ディーラーの手札:\n{dealer}");
+    println!("{}", dealer.rank().describe(Locale::Ja));
+    println!("{outcome}");
+    Some(outcome)
+}
+
+/// ディーラーと勝負した結果。
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Outcome {
+    Win,
+    Lose,
+    Tie,
+}
+
+impl Outcome {
+    /// 役とキッカーを比べ、`player` から見た結果を返す。
+    pub fn against(player: &Hands, dealer: &Hands) -> Self {
+        match player.compare(dealer) {
+            Ordering::Greater => Outcome::Win,
+            Ordering::Less => Outcome::Lose,
+            Ordering::Equal => Outcome::Tie,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Outcome::Win => "あなたの勝ちです。",
+            Outcome::Lose => "あなたの負けです。",
+            Outcome::Tie => "引き分けです。",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+/// 役やカードの名前を表示する言語。
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Locale {
+    /// 日本語。対話画面の表示に使う
+    Ja,
+    /// 英語。役は `Debug` と同じ表記になる
+    En,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -37,39 +98,226 @@ pub enum Suit {
     Spade,
 }
 
+impl Suit {
+    /// `suit as usize` の順に並べた全てのスート。
+    const ALL: [Suit; 4] = [Suit::Clover, Suit::Diamond, Suit::Heart, Suit::Spade];
+
+    /// スートの名前を返す。
+    pub fn name(self, locale: Locale) -> &'static str {
+        match (self, locale) {
+            (Suit::Clover, Locale::Ja) => "クローバー",
+            (Suit::Diamond, Locale::Ja) => "ダイヤ",
+            (Suit::Heart, Locale::Ja) => "ハート",
+            (Suit::Spade, Locale::Ja) => "スペード",
+            (Suit::Clover, Locale::En) => "Clover",
+            (Suit::Diamond, Locale::En) => "Diamond",
+            (Suit::Heart, Locale::En) => "Heart",
+            (Suit::Spade, Locale::En) => "Spade",
+        }
+    }
+}
+
+/// カードの数字。A の強さ（ホイールでは 1、それ以外では 14）はここで一元的に扱う。
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Face {
+    Ace = 1,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+}
+
+impl Face {
+    /// カードに書かれる表記 (A, 2..=10, J, Q, K) を返す。
+    fn label(self) -> String {
+        match self {
+            Face::Ace => "A".to_string(),
+            Face::Jack => "J".to_string(),
+            Face::Queen => "Q".to_string(),
+            Face::King => "K".to_string(),
+            face => face.number().to_string(),
+        }
+    }
+
+    /// 1..=13 の数字から変換する。範囲外は `None`。
+    pub const fn from_number(number: u8) -> Option<Face> {
+        use Face::*;
+
+        Some(match number {
+            1 => Ace,
+            2 => Two,
+            3 => Three,
+            4 => Four,
+            5 => Five,
+            6 => Six,
+            7 => Seven,
+            8 => Eight,
+            9 => Nine,
+            10 => Ten,
+            11 => Jack,
+            12 => Queen,
+            13 => King,
+            _ => return None,
+        })
+    }
+
+    /// A を 1 とした数字 (1..=13) を返す。
+    pub const fn number(self) -> u8 {
+        self as u8
+    }
+
+    /// A を最も強いカードとして扱った比較用の値 (2..=14) を返す。
+    pub const fn high_value(self) -> u8 {
+        match self {
+            Face::Ace => 14,
+            face => face as u8,
+        }
+    }
+
+    /// ストレート判定用のビットを返す。
+    /// A はホイール (A2345) の 1 とブロードウェイ (TJQKA) の 14 の両方に立てる。
+    const fn straight_bits(self) -> u16 {
+        match self {
+            Face::Ace => 1 << 1 | 1 << 14,
+            face => 1 << face as u8,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct Card {
     suit: Suit,
-    number: u8,
+    face: Face,
 }
 
 impl Display for Card {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CardError {
+    /// 数字が 1..=13 の範囲外
+    InvalidNumber(u8),
+    /// スートを表す文字が C/D/H/S のいずれでもない
+    InvalidSuit(char),
+    /// 文字列がカードの形式になっていない
+    InvalidFormat(String),
+}
+
+impl Card {
+    /// const で実行され、範囲外はコンパイルエラーになる。
+    /// 実行時の入力から作る場合は [`Card::try_new`] を使う。
+    pub const fn new(suit: Suit, number: u8) -> Self {
+        match Face::from_number(number) {
+            Some(face) => Self { face, suit },
+            None => panic!("card number must be 1..=13"),
+        }
+    }
+
+    /// 範囲外の数字に対して panic せず、エラーを返す。
+    pub const fn try_new(suit: Suit, number: u8) -> Result<Self, CardError> {
+        match Face::from_number(number) {
+            Some(face) => Ok(Self { face, suit }),
+            None => Err(CardError::InvalidNumber(number)),
+        }
+    }
+
+    /// `Display` と同じ表記を `w` に直接書き込む。`String` を作らずにバッファへ追記できる。
+    pub fn write_to(&self, w: &mut impl fmt::Write) -> fmt::Result {
         let suit_emoji = match self.suit {
             Suit::Clover => "♣️",
             Suit::Diamond => "♦️",
             Suit::Heart => "❤️",
             Suit::Spade => "♠️",
         };
+        w.write_str(suit_emoji)?;
+
+        match self.face {
+            Face::Ace => w.write_str("A"),
+            Face::Jack => w.write_str("J"),
+            Face::Queen => w.write_str("Q"),
+            Face::King => w.write_str("K"),
+            face => write!(w, "{}", face.number()),
+        }
+    }
 
-        let num_str: &str = match self.number {
-            1 => "A",
-            11 => "J",
-            12 => "Q",
-            13 => "K",
-            _ => return write!(f, "{}{}", suit_emoji, self.number),
-        };
-        write!(f, "{}{}", suit_emoji, num_str)
+    /// A を 1 とした数字 (1..=13) を返す。
+    pub const fn number(&self) -> u8 {
+        self.face.number()
     }
-}
 
-impl Card {
-    /// const で実行され、範囲外はコンパイルエラーになる。
-    pub const fn new(suit: Suit, number: u8) -> Self {
-        if !(1 <= number && number <= 13) {
-            panic!("card number must be 1..=13");
+    /// スートの名前と数字で、`ハートのA` や `Heart A` のように表す。
+    pub fn describe(&self, locale: Locale) -> String {
+        let suit = self.suit.name(locale);
+        match locale {
+            Locale::Ja => format!("{}の{}", suit, self.face.label()),
+            Locale::En => format!("{} {}", suit, self.face.label()),
         }
-        Self { number, suit }
+    }
+
+    /// A を最も強いカードとした比較用の値 (2..=14) を返す。
+    pub const fn high_value(&self) -> u8 {
+        self.face.high_value()
+    }
+
+    /// スートを無視し、A を最も強いカードとして数字の強さを比較する。
+    pub fn cmp_rank(&self, other: &Card) -> Ordering {
+        self.high_value().cmp(&other.high_value())
+    }
+
+    /// 52 枚全てのカードを、数字ごとに Clover, Diamond, Heart, Spade の順で返す。
+    pub fn all() -> Vec<Card> {
+        use Suit::*;
+
+        (1..=13)
+            .flat_map(|i| {
+                [
+                    Card::new(Clover, i),
+                    Card::new(Diamond, i),
+                    Card::new(Heart, i),
+                    Card::new(Spade, i),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// `S1`、`hq` のように、スート (C/D/H/S) と数字 (1..=13 または A/J/Q/K) を続けた文字列から変換する。
+/// 大文字小文字は区別しない。
+impl FromStr for Card {
+    type Err = CardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let suit = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => Suit::Clover,
+            Some('D') => Suit::Diamond,
+            Some('H') => Suit::Heart,
+            Some('S') => Suit::Spade,
+            Some(c) => return Err(CardError::InvalidSuit(c)),
+            None => return Err(CardError::InvalidFormat(s.to_string())),
+        };
+
+        let number = match chars.as_str().to_ascii_uppercase().as_str() {
+            "A" => 1,
+            "J" => 11,
+            "Q" => 12,
+            "K" => 13,
+            n => n
+                .parse::<u8>()
+                .map_err(|_| CardError::InvalidFormat(s.to_string()))?,
+        };
+        Card::try_new(suit, number)
     }
 }
 
@@ -78,6 +326,7 @@ const fn card(suit: Suit, number: u8) -> Card {
     Card::new(suit, number)
 }
 
+#[derive(Debug, PartialEq, Clone)]
 pub struct Deck {
     cards: Vec<Card>,
 }
@@ -85,21 +334,14 @@ pub struct Deck {
 impl Deck {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        use Suit::*;
+        Self::with_rng(&mut rand::rng())
+    }
 
-        let mut cards: Vec<_> = (1..=13)
-            .flat_map(|i| {
-                [
-                    Card::new(Clover, i),
-                    Card::new(Diamond, i),
-                    Card::new(Heart, i),
-                    Card::new(Spade, i),
-                ]
-            })
-            .collect();
+    /// `rng`でシャッフルしたデッキを作る。シードを固定した乱数生成器を渡すと、毎回同じ順序になる。
+    pub fn with_rng<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let mut cards = Card::all();
 
-        let mut rng = rand::rng();
-        cards.shuffle(&mut rng);
+        cards.shuffle(rng);
 
         debug_assert!(cards.len() == 52);
 
@@ -109,9 +351,40 @@ impl Deck {
     pub fn draw(&mut self) -> Card {
         self.cards.pop().unwrap()
     }
+
+    /// 残りの枚数を返す。
+    pub fn len(&self) -> usize {
+        self.cards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cards.is_empty()
+    }
+
+    /// `cards`を、指定した順に次に引かれるよう山札の上に移す。
+    ///
+    /// `cards`がデッキに残っていない場合(同じカードを2回指定した場合を含む)は、
+    /// デッキを変更せずにそのカードを`Err`で返す。
+    pub fn stack_top(&mut self, cards: &[Card]) -> Result<(), Card> {
+        let mut rest = self.cards.clone();
+        for card in cards {
+            let i = rest.iter().position(|c| c == card).ok_or(*card)?;
+            rest.remove(i);
+        }
+
+        // `draw`は末尾から引くため、逆順に積む
+        rest.extend(cards.iter().rev());
+        self.cards = rest;
+        Ok(())
+    }
+
+    /// 残りのカードを、`draw`で引かれる順に返す。デッキは消費しない。
+    pub fn iter(&self) -> impl Iterator<Item = &Card> {
+        self.cards.iter().rev()
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub enum Rank {
     HighCard(u8),
     OnePair,
@@ -123,50 +396,74 @@ pub enum Rank {
     FourCard,
     StraightFlush,
     RoyalStraightFlush,
+    /// ジョーカーを含む手札でのみ成立する、同じ数字5枚。数字は A を 1 とする
+    FiveOfAKind(u8),
 }
 
 impl Rank {
-    fn evaluate(hands: &Hands) -> Rank {
-        let stats = HandStats::from(hands);
-
-        if stats.is_royal_straight_flush() {
-            return Rank::RoyalStraightFlush;
-        }
-        if stats.is_straight_flush() {
-            return Rank::StraightFlush;
-        }
-        if stats.is_four_card() {
-            return Rank::FourCard;
+    /// 役の名前を返す。ハイカードとファイブカードは、数字を括弧内に添える。
+    pub fn describe(&self, locale: Locale) -> String {
+        if locale == Locale::En {
+            return format!("{self:?}");
         }
-        if stats.is_full_house() {
-            return Rank::FullHouse;
-        }
-        if stats.is_flush() {
-            return Rank::Flush;
-        }
-        if stats.is_straight() {
-            return Rank::Straight;
-        }
-        if stats.is_three_card() {
-            return Rank::ThreeCard;
-        }
-        if stats.is_two_pair() {
-            return Rank::TwoPair;
+
+        let label = |n: u8| Face::from_number(n).map_or(n.to_string(), Face::label);
+        match self {
+            Rank::HighCard(n) => format!("ハイカード ({})", label(*n)),
+            Rank::OnePair => "ワンペア".to_string(),
+            Rank::TwoPair => "ツーペア".to_string(),
+            Rank::ThreeCard => "スリーカード".to_string(),
+            Rank::Straight => "ストレート".to_string(),
+            Rank::Flush => "フラッシュ".to_string(),
+            Rank::FullHouse => "フルハウス".to_string(),
+            Rank::FourCard => "フォーカード".to_string(),
+            Rank::StraightFlush => "ストレートフラッシュ".to_string(),
+            Rank::RoyalStraightFlush => "ロイヤルストレートフラッシュ".to_string(),
+            Rank::FiveOfAKind(n) => format!("ファイブカード ({})", label(*n)),
         }
-        if stats.is_one_pair() {
-            return Rank::OnePair;
+    }
+
+    /// 役の強さを返す。大きいほど強い。
+    fn strength(&self) -> u8 {
+        match self {
+            Rank::HighCard(_) => 0,
+            Rank::OnePair => 1,
+            Rank::TwoPair => 2,
+            Rank::ThreeCard => 3,
+            Rank::Straight => 4,
+            Rank::Flush => 5,
+            Rank::FullHouse => 6,
+            Rank::FourCard => 7,
+            Rank::StraightFlush => 8,
+            Rank::RoyalStraightFlush => 9,
+            Rank::FiveOfAKind(_) => 10,
         }
+    }
 
-        Rank::HighCard(stats.highest)
+    fn evaluate(hands: &Hands) -> Rank {
+        HandStats::from(hands).rank()
     }
 }
 
 #[derive(Debug, Clone)]
 struct HandStats {
-    counts: [u8; 14],
+    /// 比較用の値 (A = 14) ごとの枚数。0, 1 は未使用。
+    /// ジョーカーは枚数の最も多い組に加えて数える
+    counts: [u8; 15],
+    /// ジョーカーの枚数
+    jokers: u8,
     highest: u8,
     flush: bool,
-    straight: bool,
+    /// ストレートが成立している場合、その最も高いカードの比較用の値
+    straight: Option<u8>,
+    straight_flush: bool,
+    royal_straight_flush: bool,
+    /// [`Face::straight_bits`] で作った、持っている数字のビット列
+    rank_mask: u16,
+    /// スート (`suit as usize`) ごとの枚数
+    suit_counts: [u8; 4],
+    /// スートごとに、持っている数字を [`Face::straight_bits`] で記録したビット列
+    suit_masks: [u16; 4],
     pairs: u8,
     triples: u8,
     quads: u8,
@@ -174,24 +471,57 @@ struct HandStats {
 
 impl HandStats {
     fn from(hands: &Hands) -> Self {
-        let mut counts: [u8; 14] = [0; 14]; // 0 は未使用、1..13 を利用
+        Self::from_cards(&hands[..])
+    }
+
+    /// 5枚以上の任意枚数のカードから統計を作る（7枚評価用）。
+    ///
+    /// - フラッシュ: 同じスートが5枚以上
+    /// - ストレート: 重複を除いたランクに連続する5つが含まれる
+    /// - ストレートフラッシュ: フラッシュのスート内だけでストレートが成立する
+    fn from_cards(cards: &[Card]) -> Self {
+        debug_assert!(cards.len() >= 5, "at least 5 cards are required");
+        Self::with_jokers(cards, 0)
+    }
+
+    /// ジョーカー以外のカード `cards` と `jokers` 枚のジョーカーから統計を作る。
+    ///
+    /// ジョーカーは任意のカードとして扱う (手札にあるカードと同じカードにもなれる)。
+    /// - 同じ数字の組: 枚数が最も多く、その中で最も強い数字に加える (ジョーカーのみなら A)
+    /// - フラッシュ・ストレート: 足りないカードがジョーカーの枚数以下なら成立する
+    fn with_jokers(cards: &[Card], jokers: u8) -> Self {
+        let mut counts: [u8; 15] = [0; 15];
         let mut suit_counts: [u8; 4] = [0; 4];
-        let mut numbers: [u8; 5] = [0; 5];
+        // スートごとに、持っている数字を Face::straight_bits で記録する
+        let mut suit_masks: [u16; 4] = [0; 4];
 
-        for (idx, card) in hands.iter().enumerate() {
-            counts[card.number as usize] += 1;
+        for card in cards {
+            counts[card.face.high_value() as usize] += 1;
             suit_counts[card.suit as usize] += 1;
-            numbers[idx] = card.number;
+            suit_masks[card.suit as usize] |= card.face.straight_bits();
+        }
+        if jokers > 0 {
+            let (_, value) = (2..=14).map(|v| (counts[v], v)).max().unwrap();
+            counts[value] += jokers;
         }
 
-        numbers.sort_unstable();
-        let flush = suit_counts.iter().any(|&c| c == 5);
-        let highest = *numbers.last().unwrap();
+        let rank_mask = suit_masks.iter().fold(0, |acc, m| acc | m);
+        let flush_mask = suit_masks
+            .iter()
+            .zip(suit_counts)
+            .find(|&(_, c)| c + jokers >= 5)
+            .map(|(&m, _)| m);
+        // 最も強いカードを A を 1 とした数字で保持する。ハイカードはジョーカーを含まない手札でのみ成立する
+        let highest = cards
+            .iter()
+            .map(|c| c.face)
+            .max_by_key(|f| f.high_value())
+            .map_or(1, Face::number);
 
         let mut pairs = 0u8;
         let mut triples = 0u8;
         let mut quads = 0u8;
-        for &c in counts.iter().skip(1) {
+        for &c in counts.iter() {
             match c {
                 2 => pairs += 1,
                 3 => triples += 1,
@@ -202,33 +532,177 @@ impl HandStats {
 
         HandStats {
             counts,
+            jokers,
             highest,
-            flush,
-            straight: Self::calc_straight(numbers),
+            flush: flush_mask.is_some(),
+            straight: Self::calc_straight(rank_mask, jokers),
+            straight_flush: flush_mask.is_some_and(|m| Self::calc_straight(m, jokers).is_some()),
+            royal_straight_flush: flush_mask
+                .is_some_and(|m| Self::calc_straight(m, jokers) == Some(14)),
+            rank_mask,
+            suit_counts,
+            suit_masks,
             pairs,
             triples,
             quads,
         }
     }
 
+    /// 成立している最も強い役を返す。
+    fn rank(&self) -> Rank {
+        if let Some(value) = self.five_of_a_kind() {
+            return Rank::FiveOfAKind(if value == 14 { 1 } else { value });
+        }
+        if self.is_royal_straight_flush() {
+            return Rank::RoyalStraightFlush;
+        }
+        if self.is_straight_flush() {
+            return Rank::StraightFlush;
+        }
+        if self.is_four_card() {
+            return Rank::FourCard;
+        }
+        if self.is_full_house() {
+            return Rank::FullHouse;
+        }
+        if self.is_flush() {
+            return Rank::Flush;
+        }
+        if self.is_straight() {
+            return Rank::Straight;
+        }
+        if self.is_three_card() {
+            return Rank::ThreeCard;
+        }
+        if self.is_two_pair() {
+            return Rank::TwoPair;
+        }
+        if self.is_one_pair() {
+            return Rank::OnePair;
+        }
+
+        Rank::HighCard(self.highest)
+    }
+
+    /// `target` の役に届くまでに足りないカードを英語で説明する。
+    ///
+    /// 既に `target` 以上の役が成立している場合はその旨を返す。
+    /// 足りない枚数が最も少ない揃え方を示し、候補が複数ある場合は `or` で並べる。
+    fn missing_for(&self, target: Rank) -> String {
+        let current = self.rank();
+        if current.strength() >= target.strength() {
+            return format!("already has {current:?}");
+        }
+
+        match target {
+            Rank::HighCard(_) => unreachable!("every hand is at least a high card"),
+            Rank::OnePair => self.missing_groups(&[2], "a pair"),
+            Rank::TwoPair => self.missing_groups(&[2, 2], "two pair"),
+            Rank::ThreeCard => self.missing_groups(&[3], "three of a kind"),
+            Rank::FullHouse => self.missing_groups(&[3, 2], "a full house"),
+            Rank::FourCard => self.missing_groups(&[4], "four of a kind"),
+            Rank::Flush => {
+                let (count, suit) = Suit::ALL
+                    .iter()
+                    .map(|&suit| (self.suit_counts[suit as usize], suit))
+                    .max_by_key(|&(count, _)| count)
+                    .unwrap();
+                let need = 5 - count;
+                format!(
+                    "needs {} more {} for a flush",
+                    count_word(need),
+                    plural(suit.name(Locale::En), need)
+                )
+            }
+            Rank::Straight => {
+                let draws = Self::straight_draws(self.rank_mask, 1..=10);
+                let cards = join_draws(&draws, |v| with_article(&number_name(v)));
+                format!("needs {cards} to complete the straight")
+            }
+            Rank::StraightFlush | Rank::RoyalStraightFlush => {
+                let (lows, name) = if target == Rank::RoyalStraightFlush {
+                    (10..=10, "royal flush")
+                } else {
+                    (1..=10, "straight flush")
+                };
+                let (suit, draws) = Suit::ALL
+                    .iter()
+                    .map(|&suit| {
+                        let mask = self.suit_masks[suit as usize];
+                        (suit, Self::straight_draws(mask, lows.clone()))
+                    })
+                    .min_by_key(|(_, draws)| draws[0].len())
+                    .unwrap();
+                let cards = join_draws(&draws, |v| {
+                    format!(
+                        "the {} of {}",
+                        number_name(v),
+                        plural(suit.name(Locale::En), 2)
+                    )
+                });
+                format!("needs {cards} to complete the {name}")
+            }
+            Rank::FiveOfAKind(_) => "five of a kind needs jokers".to_string(),
+        }
+    }
+
+    /// 枚数の多い組から順に `sizes` の枚数まで揃えるのに、足りないカードを説明する。
+    fn missing_groups(&self, sizes: &[u8], label: &str) -> String {
+        let needs: Vec<String> = sizes
+            .iter()
+            .zip(self.groups())
+            .filter(|&(&size, (count, _))| size > count)
+            .map(|(&size, (count, value))| {
+                let need = size - count;
+                format!(
+                    "{} more {}",
+                    count_word(need),
+                    plural(&number_name(value), need)
+                )
+            })
+            .collect();
+        format!("needs {} for {label}", needs.join(" and "))
+    }
+
+    /// 最低が `lows` のストレートのうち、足りないカードが最も少ないものについて、
+    /// 足りないカードの比較用の値 (A = 14) を返す。複数ある場合は強いストレートから順に並べる。
+    fn straight_draws(mask: u16, lows: RangeInclusive<u8>) -> Vec<Vec<u8>> {
+        let draws: Vec<Vec<u8>> = lows
+            .rev()
+            .map(|low| {
+                (low..low + 5)
+                    .filter(|v| mask & 1 << v == 0)
+                    .map(|v| if v == 1 { 14 } else { v })
+                    .collect()
+            })
+            .collect();
+        let fewest = draws.iter().map(Vec::len).min().unwrap();
+        draws.into_iter().filter(|d| d.len() == fewest).collect()
+    }
+
+    /// ジョーカーと合わせて同じ数字が5枚ある場合、その比較用の値を返す。
+    fn five_of_a_kind(&self) -> Option<u8> {
+        (2..=14).find(|&v| self.counts[v as usize] >= 5)
+    }
+
     fn is_one_pair(&self) -> bool {
         self.pairs == 1
     }
 
     fn is_two_pair(&self) -> bool {
-        self.pairs == 2
+        self.pairs >= 2
     }
 
     fn is_three_card(&self) -> bool {
-        self.triples == 1
+        self.triples >= 1
     }
 
     fn is_four_card(&self) -> bool {
-        self.quads == 1
+        self.quads >= 1
     }
 
     fn is_full_house(&self) -> bool {
-        self.triples == 1 && self.pairs == 1
+        self.triples >= 1 && (self.pairs >= 1 || self.triples >= 2)
     }
 
     fn is_flush(&self) -> bool {
@@ -236,35 +710,67 @@ impl HandStats {
     }
 
     fn is_straight(&self) -> bool {
-        self.straight
+        self.straight.is_some()
     }
 
     fn is_straight_flush(&self) -> bool {
-        self.is_flush() && self.is_straight()
+        self.straight_flush
     }
 
     fn is_royal_straight_flush(&self) -> bool {
-        self.is_straight_flush()
-            && self.counts[1] == 1
-            && self.counts[10] == 1
-            && self.counts[11] == 1
-            && self.counts[12] == 1
-            && self.counts[13] == 1
+        self.royal_straight_flush
     }
 
-    fn calc_straight(nums: [u8; 5]) -> bool {
-        // 通常ストレート: 連続差がすべて 1
-        let consecutive = nums.windows(2).all(|w| w[1] == w[0] + 1);
-        if consecutive {
-            return true;
+    /// 同じ役同士の優劣を決めるタイブレーク列を返す。先頭ほど優先される。
+    /// 5枚の手札を前提とし、A は 14 として扱う。
+    ///
+    /// - ストレート系: 最も高いカードのみ（ホイール A2345 は 5）
+    /// - フラッシュ: 数字の大きい順。ジョーカーは手札にない最も強い数字として扱う
+    /// - それ以外: 枚数の多い組から順に、同じ枚数なら数字の大きい順
+    fn tiebreak(&self) -> Vec<u8> {
+        match self.rank() {
+            Rank::Straight | Rank::StraightFlush | Rank::RoyalStraightFlush => {
+                return vec![self.straight.unwrap()];
+            }
+            Rank::Flush if self.jokers > 0 => {
+                let (present, absent): (Vec<u8>, Vec<u8>) =
+                    (2..=14).rev().partition(|&v| self.rank_mask & 1 << v != 0);
+                let mut values: Vec<u8> = absent.into_iter().take(self.jokers as usize).collect();
+                values.extend(present);
+                values.sort_unstable_by(|a, b| b.cmp(a));
+                return values;
+            }
+            _ => {}
         }
 
-        // 例外パターン: ホイール A2345、ブロードウェイ TJQKA
-        nums == [1, 2, 3, 4, 5] || nums == [1, 10, 11, 12, 13]
+        self.groups().into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// 同じ数字のカードの組を `(枚数, 比較用の値)` として、枚数の多い順、同じ枚数なら数字の大きい順に返す。
+    fn groups(&self) -> Vec<(u8, u8)> {
+        let mut groups: Vec<(u8, u8)> = (2..=14)
+            .filter(|&v| self.counts[v as usize] > 0)
+            .map(|v| (self.counts[v as usize], v))
+            .collect();
+        groups.sort_unstable_by(|a, b| b.cmp(a));
+        groups
+    }
+
+    /// [`Face::straight_bits`] で作った `mask` に、`jokers` 枚のジョーカーで補って
+    /// 連続する5つの数字が揃うか判定し、揃う場合はその最も高いカードの比較用の値を返す（ホイール A2345 は 5）。
+    fn calc_straight(mask: u16, jokers: u8) -> Option<u8> {
+        (1..=10u8).rev().find_map(|low| {
+            let run: u16 = 0b11111 << low;
+            (5 - (mask & run).count_ones() as u8 <= jokers).then_some(low + 4)
+        })
     }
 }
 
-#[derive(Debug)]
+/// 5枚の手札。
+///
+/// `==` は並び順も含めて比較する。並び順を無視して同じカードの組かどうかを
+/// 調べる場合は [`Hands::same_cards`] を使う。
+#[derive(Debug, PartialEq, Clone)]
 pub struct Hands([Card; 5]);
 
 impl Deref for Hands {
@@ -280,6 +786,38 @@ impl DerefMut for Hands {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum HandsError {
+    /// カードが5枚ではない
+    WrongCount(usize),
+    /// 同じカードが複数含まれている
+    Duplicate(Card),
+}
+
+impl From<Hands> for Vec<Card> {
+    fn from(hands: Hands) -> Self {
+        hands.0.to_vec()
+    }
+}
+
+impl TryFrom<Vec<Card>> for Hands {
+    type Error = HandsError;
+
+    fn try_from(cards: Vec<Card>) -> Result<Self, Self::Error> {
+        let cards: [Card; 5] = cards
+            .try_into()
+            .map_err(|v: Vec<Card>| HandsError::WrongCount(v.len()))?;
+
+        for (i, card) in cards.iter().enumerate() {
+            if cards[..i].contains(card) {
+                return Err(HandsError::Duplicate(*card));
+            }
+        }
+
+        Ok(Hands(cards))
+    }
+}
+
 impl Hands {
     fn new_from_deck(deck: &mut Deck) -> Self {
         Hands([
@@ -291,16 +829,96 @@ impl Hands {
         ])
     }
 
-    fn exchange(&mut self, deck: &mut Deck, card: Card) {
-        let i = self.iter().position(|&x| x == card).unwrap();
-
-        self[i] = deck.draw();
+    /// `indices`の位置のカードを`deck`から引いたカードと入れ替え、捨てたカードを`indices`の順に返す。
+    ///
+    /// 他の位置のカードは動かさない。同じ位置が複数回指定された場合は1回だけ入れ替える。
+    pub fn exchange(&mut self, deck: &mut Deck, indices: &[usize]) -> Vec<Card> {
+        let mut discarded = Vec::with_capacity(indices.len());
+        for (n, &i) in indices.iter().enumerate() {
+            if indices[..n].contains(&i) {
+                continue;
+            }
+            discarded.push(std::mem::replace(&mut self[i], deck.draw()));
+        }
+        discarded
     }
 
     fn rank(&self) -> Rank {
         Rank::evaluate(self)
     }
 
+    /// `{}` と同じ表記を `w` に直接書き込む。大量の手札をログに出す場合に、1枚ごとの `String` を作らずに済む。
+    pub fn write_to(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        for (i, card) in self.0.iter().enumerate() {
+            write!(w, "{}. ", i + 1)?; // 1-indexed;
+            card.write_to(w)?;
+            w.write_str("\n")?;
+        }
+        Ok(())
+    }
+
+    /// `{:#}` と同じ1行の表記を `w` に直接書き込む。
+    pub fn write_compact_to(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        write_compact(w, &self.0)
+    }
+
+    /// A を最も強いカードとして、最も強いカードを返す。
+    /// 同じ数字のカードが複数ある場合は、手札で先にあるカードを返す。
+    pub fn highest_card(&self) -> Card {
+        *self.iter().min_by_key(|c| Reverse(c.high_value())).unwrap()
+    }
+
+    /// A を最も強いカードとして、最も弱いカードを返す。
+    /// 同じ数字のカードが複数ある場合は、手札で先にあるカードを返す。
+    pub fn lowest_card(&self) -> Card {
+        *self.iter().min_by_key(|c| c.high_value()).unwrap()
+    }
+
+    /// 強い順に並べたカードを返す。同じ数字のカードは手札の順を保つ。
+    pub fn cards_by_rank_desc(&self) -> Vec<Card> {
+        let mut cards = self.to_vec();
+        cards.sort_by(|a, b| b.cmp_rank(a));
+        cards
+    }
+
+    /// 並び順を無視して、同じカードの組で構成されているか判定する。
+    pub fn same_cards(&self, other: &Hands) -> bool {
+        self.iter().all(|c| other.contains(c)) && other.iter().all(|c| self.contains(c))
+    }
+
+    /// 役の強さ、同じ役ならタイブレーク列で手札を比較する。
+    fn compare(&self, other: &Hands) -> Ordering {
+        self.rank()
+            .strength()
+            .cmp(&other.rank().strength())
+            .then_with(|| self.tiebreak().cmp(&other.tiebreak()))
+    }
+
+    /// `other`より強い手札か判定する。引き分けの場合は`false`を返す。
+    pub fn beats(&self, other: &Hands) -> bool {
+        self.compare(other) == Ordering::Greater
+    }
+
+    fn tiebreak(&self) -> Vec<u8> {
+        HandStats::from(self).tiebreak()
+    }
+
+    /// `target` の役に届くまでに足りないカードを英語で説明する。
+    /// 例えばハートが4枚の手札では、フラッシュに対して`"needs one more Heart for a flush"`を返す。
+    pub fn missing_for(&self, target: Rank) -> String {
+        HandStats::from(self).missing_for(target)
+    }
+
+    /// 役とタイブレーク列を計算し、手札とともに保持する。
+    pub fn ranked(self) -> RankedHand {
+        let stats = HandStats::from(&self);
+        RankedHand {
+            rank: Rank::evaluate(&self),
+            tiebreak: stats.tiebreak(),
+            hands: self,
+        }
+    }
+
     /// 連続した5枚を生成する。10 を渡すとロイヤル (10,J,Q,K,A) になる。
     pub const fn straight(suit: Suit, start: u8) -> Self {
         const fn wrap(n: u8) -> u8 {
@@ -327,47 +945,355 @@ impl Hands {
     }
 }
 
-impl Display for Hands {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (i, card) in self.0.iter().enumerate() {
-            write!(f, "{}. ", i + 1)?; // 1-indexed;
-            card.fmt(f)?;
-            writeln!(f)?;
-        }
-        Ok(())
-    }
+/// 役の計算結果を保持する手札。
+///
+/// [`Hands::rank`]は呼ぶたびに役を計算し直すため、
+/// 何度も比較するショーダウンなどではこちらを使う。
+#[derive(Debug)]
+pub struct RankedHand {
+    hands: Hands,
+    rank: Rank,
+    tiebreak: Vec<u8>,
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+impl RankedHand {
+    pub fn hands(&self) -> &Hands {
+        &self.hands
+    }
 
-    // テスト用の簡潔な手札リテラル。配列長が 5 であることは型で保証される。
-    macro_rules! hand {
-        ( $( $suit:ident $num:expr ),+ $(,)? ) => {
-            Hands([ $( card(Suit::$suit, $num) ),+ ])
-        };
+    pub fn rank(&self) -> &Rank {
+        &self.rank
     }
 
-    #[test]
-    fn straight() {
-        let hands = hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 5];
-        assert_eq!(hands.rank(), Rank::Straight);
+    /// [`Hands::compare`]と同じ順序で、計算済みの役を使って比較する。
+    pub fn compare(&self, other: &RankedHand) -> Ordering {
+        self.rank
+            .strength()
+            .cmp(&other.rank.strength())
+            .then_with(|| self.tiebreak.cmp(&other.tiebreak))
     }
+}
 
-    #[test]
-    fn straight_flush() {
-        let hands = Hands::straight(Suit::Spade, 5);
-        assert_eq!(hands.rank(), Rank::StraightFlush);
+/// 1..=4 を英単語にする。それ以外は数字のまま返す。
+fn count_word(n: u8) -> String {
+    match n {
+        1 => "one".to_string(),
+        2 => "two".to_string(),
+        3 => "three".to_string(),
+        4 => "four".to_string(),
+        n => n.to_string(),
     }
+}
 
-    #[test]
-    fn royal_straight_flush() {
-        let hands = Hands::royal(Suit::Diamond);
-        assert_eq!(hands.rank(), Rank::RoyalStraightFlush);
+/// `n` が2以上なら名前を複数形にする。
+fn plural(name: &str, n: u8) -> String {
+    if n == 1 {
+        name.to_string()
+    } else {
+        format!("{name}s")
     }
+}
 
-    #[test]
+/// 名前に不定冠詞を付ける (`a 6`、`an Ace`)。
+fn with_article(name: &str) -> String {
+    if name.starts_with(['A', '8']) {
+        format!("an {name}")
+    } else {
+        format!("a {name}")
+    }
+}
+
+/// [`HandStats::straight_draws`] の結果を文にする。
+///
+/// 1枚で完成する場合はどれか1枚があればよいため `or` で並べ、
+/// 2枚以上必要な場合は最も強いストレートに必要なカードを `and` で並べる。
+fn join_draws(draws: &[Vec<u8>], name: impl Fn(u8) -> String) -> String {
+    if draws[0].len() == 1 {
+        let mut values: Vec<u8> = draws.iter().map(|d| d[0]).collect();
+        values.sort_unstable();
+        values.dedup();
+        values
+            .into_iter()
+            .map(name)
+            .collect::<Vec<_>>()
+            .join(" or ")
+    } else {
+        draws[0]
+            .iter()
+            .map(|&v| name(v))
+            .collect::<Vec<_>>()
+            .join(" and ")
+    }
+}
+
+/// 比較用の数字 (A = 14) を英語名にする。
+fn number_name(value: u8) -> String {
+    match value {
+        14 => "Ace".to_string(),
+        11 => "Jack".to_string(),
+        12 => "Queen".to_string(),
+        13 => "King".to_string(),
+        n => n.to_string(),
+    }
+}
+
+/// 最も強い手札を持つプレイヤーの番号 (0-indexed) を返す。同点の場合は先頭を優先する。
+pub fn showdown(hands: &[Hands]) -> usize {
+    let mut winner = 0;
+    for (i, h) in hands.iter().enumerate().skip(1) {
+        if h.compare(&hands[winner]) == Ordering::Greater {
+            winner = i;
+        }
+    }
+    winner
+}
+
+/// プレイヤーの番号 (0-indexed) を強い順に並べる。
+///
+/// 同点のプレイヤーは同じグループにまとめ、グループ内は番号順に並べる。
+/// `Hands` の `==` は並び順を含めて比較するため、`Ord` は実装せず [`Hands::compare`] の順序を使う。
+pub fn rank_players(hands: &[Hands]) -> Vec<Vec<usize>> {
+    let ranked: Vec<_> = hands.iter().cloned().map(Hands::ranked).collect();
+
+    let mut order: Vec<usize> = (0..ranked.len()).collect();
+    // 安定ソートのため、同点のプレイヤーは番号順のまま残る
+    order.sort_by(|&a, &b| ranked[b].compare(&ranked[a]));
+
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for i in order {
+        match groups.last_mut() {
+            Some(group) if ranked[group[0]].compare(&ranked[i]) == Ordering::Equal => group.push(i),
+            _ => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+/// 手札とチップを持つプレイヤー。
+#[derive(Debug, Clone)]
+pub struct Player {
+    hands: Hands,
+    chips: u32,
+}
+
+impl Player {
+    pub fn new(hands: Hands, chips: u32) -> Self {
+        Self { hands, chips }
+    }
+
+    pub fn hands(&self) -> &Hands {
+        &self.hands
+    }
+
+    pub fn chips(&self) -> u32 {
+        self.chips
+    }
+}
+
+/// プレイヤーとポットを管理するテーブル。
+///
+/// 1ゲームは [`Table::ante`] でチップを集め、[`Table::settle`] で勝者に支払う。
+#[derive(Debug)]
+pub struct Table {
+    players: Vec<Player>,
+    pot: u32,
+}
+
+impl Table {
+    pub fn new(players: Vec<Player>) -> Self {
+        Self { players, pot: 0 }
+    }
+
+    /// `deck` から `players` 人に手札を配り、それぞれ `chips` 枚のチップを持たせる。
+    pub fn deal(deck: &mut Deck, players: usize, chips: u32) -> Self {
+        let players = (0..players)
+            .map(|_| Player::new(Hands::new_from_deck(deck), chips))
+            .collect();
+        Self::new(players)
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    pub fn pot(&self) -> u32 {
+        self.pot
+    }
+
+    /// 全員から `amount` 枚ずつ参加費を集める。足りないプレイヤーは持っている全額を出す。
+    pub fn ante(&mut self, amount: u32) {
+        for player in &mut self.players {
+            let paid = amount.min(player.chips);
+            player.chips -= paid;
+            self.pot += paid;
+        }
+    }
+
+    /// ポットを全て `winner` (0-indexed) に支払う。
+    pub fn payout(&mut self, winner: usize) {
+        self.players[winner].chips += self.pot;
+        self.pot = 0;
+    }
+
+    /// [`showdown`] で勝者を決めてポットを支払い、勝者の番号を返す。
+    pub fn settle(&mut self) -> usize {
+        let hands: Vec<_> = self.players.iter().map(|p| p.hands.clone()).collect();
+        let winner = showdown(&hands);
+        self.payout(winner);
+        winner
+    }
+}
+
+/// 勝者がなぜ勝ったのかを説明する。
+///
+/// 次点の手札と役を比べ、同じ役ならタイブレーク列の最初に異なる位置のカードを示す。
+pub fn explain_showdown(hands: &[Hands], winner: usize) -> String {
+    let player = winner + 1;
+    let runner_up = hands
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != winner)
+        .max_by(|(_, a), (_, b)| a.compare(b));
+    let Some((other, other_hands)) = runner_up else {
+        return format!("Player {player} wins");
+    };
+
+    let rank = hands[winner].rank();
+    if rank.strength() != other_hands.rank().strength() {
+        return format!("Player {player} wins with {rank:?}");
+    }
+
+    let ours = hands[winner].tiebreak();
+    let theirs = other_hands.tiebreak();
+    match ours.iter().zip(&theirs).position(|(a, b)| a != b) {
+        Some(0) => format!(
+            "Player {player} wins with a higher card: {}",
+            number_name(ours[0])
+        ),
+        Some(i) => format!(
+            "Player {player} wins with a higher kicker: {}",
+            number_name(ours[i])
+        ),
+        None => format!("Player {player} ties with Player {}", other + 1),
+    }
+}
+
+/// ジョーカーを含む手札の役とタイブレーク列を求める。
+///
+/// `naturals`はジョーカー以外のカードで、`jokers`枚のジョーカーと合わせて5枚になる。
+/// ジョーカーは任意のカードとして扱い (手札にあるカードと同じカードにもなれる)、最も強い役を返す。
+/// 同じ数字がジョーカーと合わせて5枚になる場合は [`Rank::FiveOfAKind`] になる。
+///
+/// 合わせて5枚でない場合は [`HandsError::WrongCount`] に合計の枚数を入れて返す。
+pub fn evaluate_with_jokers(
+    naturals: &[Card],
+    jokers: usize,
+) -> Result<(Rank, Vec<u8>), HandsError> {
+    if naturals.len() + jokers != 5 {
+        return Err(HandsError::WrongCount(naturals.len() + jokers));
+    }
+
+    let stats = HandStats::with_jokers(naturals, jokers as u8);
+    Ok((stats.rank(), stats.tiebreak()))
+}
+
+/// `hands`から`discard`の位置のカードを捨て、`deck`の残りから引き直した場合の役の確率を推定する。
+///
+/// 引き直しを`trials`回シミュレーションし、各役が出た割合を返す。`deck`自体は変更しない。
+/// `deck`の残り枚数が捨てる枚数より少ない場合はpanicする。
+pub fn draw_odds<R: Rng + ?Sized>(
+    hands: &Hands,
+    discard: &[usize],
+    deck: &Deck,
+    trials: usize,
+    rng: &mut R,
+) -> HashMap<Rank, f64> {
+    assert!(
+        discard.len() <= deck.len(),
+        "not enough cards in the deck to draw"
+    );
+
+    let mut counts: HashMap<Rank, usize> = HashMap::new();
+    let mut cards = deck.cards.clone();
+    for _ in 0..trials {
+        // 先頭から捨てた枚数分だけシャッフルすれば、無作為に引いたことになる
+        let (drawn, _) = cards.partial_shuffle(rng, discard.len());
+
+        let mut hands = hands.clone();
+        for (&i, &card) in discard.iter().zip(drawn.iter()) {
+            hands[i] = card;
+        }
+        *counts.entry(hands.rank()).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(rank, count)| (rank, count as f64 / trials as f64))
+        .collect()
+}
+
+/// カードを `[♠️A ❤️10 ♣️3]` のように1行で表示する。
+fn write_compact<'a>(
+    w: &mut impl fmt::Write,
+    cards: impl IntoIterator<Item = &'a Card>,
+) -> fmt::Result {
+    w.write_str("[")?;
+    for (i, card) in cards.into_iter().enumerate() {
+        if i > 0 {
+            w.write_str(" ")?;
+        }
+        card.write_to(w)?;
+    }
+    w.write_str("]")
+}
+
+/// 残りのカードを `draw` で引かれる順に1行で表示する。
+impl Display for Deck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_compact(f, self.iter())
+    }
+}
+
+/// 番号付きで1行に1枚ずつ表示する。`{:#}` の場合はログ向けに1行で表示する。
+impl Display for Hands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return self.write_compact_to(f);
+        }
+        self.write_to(f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // テスト用の簡潔な手札リテラル。配列長が 5 であることは型で保証される。
+    macro_rules! hand {
+        ( $( $suit:ident $num:expr ),+ $(,)? ) => {
+            Hands([ $( card(Suit::$suit, $num) ),+ ])
+        };
+    }
+
+    #[test]
+    fn straight() {
+        let hands = hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 5];
+        assert_eq!(hands.rank(), Rank::Straight);
+    }
+
+    #[test]
+    fn straight_flush() {
+        let hands = Hands::straight(Suit::Spade, 5);
+        assert_eq!(hands.rank(), Rank::StraightFlush);
+    }
+
+    #[test]
+    fn royal_straight_flush() {
+        let hands = Hands::royal(Suit::Diamond);
+        assert_eq!(hands.rank(), Rank::RoyalStraightFlush);
+    }
+
+    #[test]
     fn four_card() {
         let hands = hand![Heart 9, Spade 9, Clover 9, Diamond 9, Heart 2];
         assert_eq!(hands.rank(), Rank::FourCard);
@@ -424,4 +1350,961 @@ mod test {
         let hands = hand![Heart 2, Spade 5, Diamond 7, Clover 9, Heart 12];
         assert_eq!(hands.rank(), Rank::HighCard(12));
     }
+
+    #[test]
+    fn from_cards_seven_pairs() {
+        let cards = [
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 5),
+            card(Suit::Diamond, 9),
+            card(Suit::Clover, 9),
+            card(Suit::Heart, 12),
+            card(Suit::Spade, 12),
+            card(Suit::Diamond, 2),
+        ];
+        let stats = HandStats::from_cards(&cards);
+
+        assert_eq!(stats.pairs, 3);
+        assert!(stats.is_two_pair());
+        assert!(!stats.is_flush());
+        assert!(!stats.is_straight());
+    }
+
+    #[test]
+    fn from_cards_seven_flush() {
+        let cards = [
+            card(Suit::Heart, 2),
+            card(Suit::Heart, 6),
+            card(Suit::Heart, 9),
+            card(Suit::Heart, 11),
+            card(Suit::Heart, 13),
+            card(Suit::Spade, 9),
+            card(Suit::Clover, 3),
+        ];
+        let stats = HandStats::from_cards(&cards);
+
+        assert!(stats.is_flush());
+        assert_eq!(stats.pairs, 1);
+        assert!(!stats.is_straight_flush());
+    }
+
+    #[test]
+    fn from_cards_straight_and_flush_in_different_cards() {
+        // 5-9 のストレートと、ハートのフラッシュが別々のカードで成立している
+        let cards = [
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 6),
+            card(Suit::Heart, 7),
+            card(Suit::Clover, 8),
+            card(Suit::Heart, 9),
+            card(Suit::Heart, 1),
+            card(Suit::Heart, 12),
+        ];
+        let stats = HandStats::from_cards(&cards);
+
+        assert!(stats.is_flush());
+        assert!(stats.is_straight());
+        assert!(!stats.is_straight_flush());
+    }
+
+    #[test]
+    fn missing_for_four_flush() {
+        let hands = hand![Heart 2, Heart 5, Heart 9, Heart 11, Spade 13];
+        assert_eq!(
+            hands.missing_for(Rank::Flush),
+            "needs one more Heart for a flush"
+        );
+
+        let hands = hand![Heart 2, Heart 5, Heart 9, Spade 11, Spade 13];
+        assert_eq!(
+            hands.missing_for(Rank::Flush),
+            "needs two more Hearts for a flush"
+        );
+    }
+
+    #[test]
+    fn missing_for_four_to_a_straight() {
+        let hands = hand![Heart 7, Spade 8, Clover 9, Diamond 10, Heart 13];
+        assert_eq!(
+            hands.missing_for(Rank::Straight),
+            "needs a 6 or a Jack to complete the straight"
+        );
+
+        // 内側が欠けている場合は1通り
+        let hands = hand![Heart 7, Spade 8, Clover 10, Diamond 11, Heart 2];
+        assert_eq!(
+            hands.missing_for(Rank::Straight),
+            "needs a 9 to complete the straight"
+        );
+
+        // A はホイールにもブロードウェイにも使える
+        let hands = hand![Heart 2, Spade 3, Clover 4, Diamond 5, Heart 9];
+        assert_eq!(
+            hands.missing_for(Rank::Straight),
+            "needs a 6 or an Ace to complete the straight"
+        );
+    }
+
+    #[test]
+    fn missing_for_groups() {
+        let hands = hand![Heart 13, Spade 13, Clover 4, Diamond 5, Heart 9];
+        assert_eq!(
+            hands.missing_for(Rank::ThreeCard),
+            "needs one more King for three of a kind"
+        );
+        assert_eq!(
+            hands.missing_for(Rank::FullHouse),
+            "needs one more King and one more 9 for a full house"
+        );
+        assert_eq!(
+            hands.missing_for(Rank::FourCard),
+            "needs two more Kings for four of a kind"
+        );
+        assert_eq!(hands.missing_for(Rank::OnePair), "already has OnePair");
+    }
+
+    #[test]
+    fn missing_for_straight_flush() {
+        let hands = hand![Heart 9, Heart 10, Heart 11, Heart 12, Spade 2];
+        assert_eq!(
+            hands.missing_for(Rank::StraightFlush),
+            "needs the 8 of Hearts or the King of Hearts to complete the straight flush"
+        );
+        assert_eq!(
+            hands.missing_for(Rank::RoyalStraightFlush),
+            "needs the King of Hearts and the Ace of Hearts to complete the royal flush"
+        );
+    }
+
+    #[test]
+    fn explain_showdown_second_kicker() {
+        let hands = [
+            hand![Heart 5, Spade 5, Diamond 1, Clover 12, Heart 3],
+            hand![Diamond 5, Clover 5, Spade 1, Heart 13, Spade 3],
+        ];
+
+        assert_eq!(showdown(&hands), 1);
+        assert_eq!(
+            explain_showdown(&hands, 1),
+            "Player 2 wins with a higher kicker: King"
+        );
+    }
+
+    #[test]
+    fn explain_showdown_different_rank() {
+        let hands = [
+            hand![Heart 3, Spade 3, Clover 3, Diamond 8, Heart 8],
+            hand![Heart 5, Spade 5, Diamond 7, Clover 9, Heart 11],
+        ];
+
+        assert_eq!(showdown(&hands), 0);
+        assert_eq!(explain_showdown(&hands, 0), "Player 1 wins with FullHouse");
+    }
+
+    #[test]
+    fn explain_showdown_tie() {
+        let hands = [
+            hand![Heart 2, Spade 5, Diamond 7, Clover 9, Heart 12],
+            hand![Spade 2, Heart 5, Clover 7, Diamond 9, Spade 12],
+        ];
+
+        assert_eq!(explain_showdown(&hands, 0), "Player 1 ties with Player 2");
+    }
+
+    #[test]
+    fn wheel_loses_to_six_high_straight() {
+        let wheel = hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 5];
+        let six_high = hand![Heart 2, Spade 3, Clover 4, Diamond 5, Heart 6];
+
+        assert_eq!(wheel.compare(&six_high), Ordering::Less);
+    }
+
+    #[test]
+    fn hands_vec_round_trip() {
+        let cards = vec![
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 5),
+            card(Suit::Diamond, 7),
+            card(Suit::Clover, 9),
+            card(Suit::Heart, 11),
+        ];
+
+        let hands = Hands::try_from(cards.clone()).unwrap();
+        assert_eq!(Vec::from(hands), cards);
+    }
+
+    #[test]
+    fn hands_try_from_wrong_count() {
+        let cards = vec![
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 5),
+            card(Suit::Diamond, 7),
+            card(Suit::Clover, 9),
+        ];
+
+        assert_eq!(
+            Hands::try_from(cards).unwrap_err(),
+            HandsError::WrongCount(4)
+        );
+    }
+
+    #[test]
+    fn hands_try_from_duplicate() {
+        let cards = vec![
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 5),
+            card(Suit::Diamond, 7),
+            card(Suit::Heart, 5),
+            card(Suit::Heart, 11),
+        ];
+
+        assert_eq!(
+            Hands::try_from(cards).unwrap_err(),
+            HandsError::Duplicate(card(Suit::Heart, 5))
+        );
+    }
+
+    #[test]
+    fn deck_len_decreases_per_draw() {
+        let mut deck = Deck::new();
+        assert_eq!(deck.len(), 52);
+
+        for i in 1..=10 {
+            deck.draw();
+            assert_eq!(deck.len(), 52 - i);
+        }
+        assert!(!deck.is_empty());
+    }
+
+    #[test]
+    fn deck_iter_matches_draw_order() {
+        let mut deck = Deck::new();
+        let _ = Hands::new_from_deck(&mut deck);
+        assert_eq!(deck.iter().count(), deck.len());
+
+        let remaining: Vec<Card> = deck.iter().copied().collect();
+        let drawn: Vec<Card> = (0..remaining.len()).map(|_| deck.draw()).collect();
+        assert_eq!(remaining, drawn);
+        assert!(deck.is_empty());
+    }
+
+    #[test]
+    fn ranked_hand_matches_rank() {
+        let hands = [
+            hand![Heart 3, Spade 3, Clover 3, Diamond 8, Heart 8],
+            hand![Heart 5, Spade 5, Diamond 7, Clover 9, Heart 11],
+            hand![Heart 2, Spade 5, Diamond 7, Clover 9, Heart 12],
+            Hands::royal(Suit::Spade),
+        ];
+
+        for hands in hands {
+            let rank = hands.rank();
+            let tiebreak = hands.tiebreak();
+            let ranked = hands.ranked();
+
+            assert_eq!(ranked.rank(), &rank);
+            assert_eq!(ranked.tiebreak, tiebreak);
+            assert_eq!(ranked.hands().rank(), rank);
+        }
+    }
+
+    #[test]
+    fn ranked_hand_compare_matches_hands_compare() {
+        let a = hand![Heart 5, Spade 5, Diamond 1, Clover 12, Heart 3];
+        let b = hand![Diamond 5, Clover 5, Spade 1, Heart 13, Spade 3];
+        let expected = a.compare(&b);
+
+        assert_eq!(a.ranked().compare(&b.ranked()), expected);
+        assert_eq!(expected, Ordering::Less);
+    }
+
+    #[test]
+    fn face_number_round_trip() {
+        for n in 1..=13 {
+            let face = Face::from_number(n).unwrap();
+            assert_eq!(face.number(), n);
+            assert_eq!(card(Suit::Heart, n).number(), n);
+        }
+        assert_eq!(Face::from_number(0), None);
+        assert_eq!(Face::from_number(14), None);
+        assert_eq!(Face::Ace.high_value(), 14);
+    }
+
+    #[test]
+    fn high_card_with_ace() {
+        // A はハイカードで最も強いカードとして扱う
+        let hands = hand![Heart 1, Spade 5, Diamond 7, Clover 9, Heart 13];
+        assert_eq!(hands.rank(), Rank::HighCard(1));
+    }
+
+    #[test]
+    fn ace_is_high_and_low_in_straights() {
+        let broadway = hand![Heart 10, Spade 11, Clover 12, Diamond 13, Heart 1];
+        let king_high = hand![Heart 9, Spade 10, Clover 11, Diamond 12, Heart 13];
+        let wheel = hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 5];
+
+        assert_eq!(broadway.rank(), Rank::Straight);
+        assert_eq!(broadway.compare(&king_high), Ordering::Greater);
+        assert_eq!(wheel.compare(&king_high), Ordering::Less);
+    }
+
+    #[test]
+    fn hands_eq_is_order_sensitive() {
+        let a = hand![Heart 5, Spade 5, Diamond 7, Clover 9, Heart 11];
+        let b = hand![Heart 5, Spade 5, Diamond 7, Clover 9, Heart 11];
+        let reordered = hand![Heart 11, Clover 9, Diamond 7, Spade 5, Heart 5];
+
+        assert_eq!(a, b);
+        assert_eq!(a.clone(), a);
+        assert_ne!(a, reordered);
+        assert!(a.same_cards(&reordered));
+        assert!(!a.same_cards(&Hands::royal(Suit::Heart)));
+    }
+
+    #[test]
+    fn deck_clone_is_a_snapshot() {
+        let mut deck = Deck::new();
+        let snapshot = deck.clone();
+
+        assert_eq!(deck, snapshot);
+
+        let drawn = deck.draw();
+        assert_ne!(deck, snapshot);
+        assert_eq!(deck.len(), 51);
+        assert_eq!(snapshot.len(), 52);
+        assert_eq!(snapshot.iter().next(), Some(&drawn));
+    }
+
+    #[test]
+    fn draw_odds_one_pair() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let hands = hand![Heart 9, Spade 9, Clover 2, Diamond 5, Heart 12];
+        let mut deck = Deck::with_rng(&mut rng);
+        deck.cards.retain(|c| !hands.contains(c));
+
+        // ペア以外の3枚を引き直す
+        let odds = draw_odds(&hands, &[2, 3, 4], &deck, 10_000, &mut rng);
+
+        // ペアを残しているため、役なしにはならない
+        assert!(!odds.keys().any(|rank| matches!(rank, Rank::HighCard(_))));
+        assert!(odds[&Rank::ThreeCard] > 0.0);
+        assert!(odds[&Rank::OnePair] > odds[&Rank::ThreeCard]);
+
+        let total: f64 = odds.values().sum();
+        assert!((total - 1.0).abs() < 1e-9, "total: {total}");
+    }
+
+    #[test]
+    fn deck_with_same_seed_is_reproducible() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let a = Deck::with_rng(&mut StdRng::seed_from_u64(1));
+        let b = Deck::with_rng(&mut StdRng::seed_from_u64(1));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn beats() {
+        let flush = hand![Heart 2, Heart 5, Heart 7, Heart 9, Heart 12];
+        let straight = hand![Spade 5, Heart 6, Spade 7, Clover 8, Diamond 9];
+        assert!(flush.beats(&straight));
+        assert!(!straight.beats(&flush));
+        assert!(!flush.beats(&flush));
+
+        // スートだけが異なる手札は引き分けで、どちらも勝たない
+        let other_straight = hand![Heart 5, Spade 6, Heart 7, Diamond 8, Clover 9];
+        assert!(!straight.beats(&other_straight));
+        assert!(!other_straight.beats(&straight));
+    }
+
+    #[test]
+    fn every_category_is_strictly_ordered() {
+        // 弱い順に各役の代表的な手札を並べる
+        let hands = [
+            hand![Heart 2, Spade 5, Clover 7, Diamond 9, Heart 1],
+            hand![Heart 9, Spade 9, Clover 2, Diamond 5, Heart 12],
+            hand![Heart 9, Spade 9, Clover 5, Diamond 5, Heart 12],
+            hand![Heart 9, Spade 9, Clover 9, Diamond 5, Heart 12],
+            hand![Spade 5, Heart 6, Spade 7, Clover 8, Diamond 9],
+            hand![Heart 2, Heart 5, Heart 7, Heart 9, Heart 12],
+            hand![Heart 9, Spade 9, Clover 9, Diamond 5, Heart 5],
+            hand![Heart 9, Spade 9, Clover 9, Diamond 9, Heart 5],
+            Hands::straight(Suit::Clover, 5),
+            Hands::royal(Suit::Diamond),
+        ];
+
+        let strengths: Vec<_> = hands.iter().map(|h| h.rank().strength()).collect();
+        assert_eq!(strengths, (0..10).collect::<Vec<_>>());
+
+        // 全ての組について、並び順どおりに勝敗が決まる
+        for (i, weaker) in hands.iter().enumerate() {
+            assert!(!weaker.beats(weaker), "{i} beats itself");
+            for (j, stronger) in hands.iter().enumerate().skip(i + 1) {
+                assert!(stronger.beats(weaker), "{j} should beat {i}");
+                assert!(!weaker.beats(stronger), "{i} should not beat {j}");
+            }
+        }
+    }
+
+    #[test]
+    fn deck_stack_top() {
+        let royal = Hands::royal(Suit::Heart);
+        let mut deck = Deck::new();
+        deck.stack_top(&royal[..]).unwrap();
+
+        assert_eq!(deck.len(), 52);
+        assert_eq!(Hands::new_from_deck(&mut deck), royal);
+        // 積んだカードは残りのデッキに重複しない
+        assert!(deck.iter().all(|c| !royal.contains(c)));
+    }
+
+    #[test]
+    fn deck_stack_top_missing_card() {
+        let mut deck = Deck::new();
+        let drawn = deck.draw();
+        let before = deck.clone();
+
+        assert_eq!(deck.stack_top(&[card(Suit::Spade, 1), drawn]), Err(drawn));
+        assert_eq!(deck, before);
+
+        let twice = deck.iter().next().copied().unwrap();
+        assert_eq!(deck.stack_top(&[twice, twice]), Err(twice));
+    }
+
+    #[test]
+    fn card_try_new() {
+        assert_eq!(
+            Card::try_new(Suit::Spade, 0),
+            Err(CardError::InvalidNumber(0))
+        );
+        assert_eq!(
+            Card::try_new(Suit::Spade, 14),
+            Err(CardError::InvalidNumber(14))
+        );
+        for number in 1..=13 {
+            assert_eq!(
+                Card::try_new(Suit::Spade, number),
+                Ok(card(Suit::Spade, number))
+            );
+        }
+    }
+
+    #[test]
+    fn card_from_str() {
+        assert_eq!("S1".parse(), Ok(card(Suit::Spade, 1)));
+        assert_eq!("hq".parse(), Ok(card(Suit::Heart, 12)));
+        assert_eq!("D10".parse(), Ok(card(Suit::Diamond, 10)));
+        assert_eq!("CA".parse(), Ok(card(Suit::Clover, 1)));
+
+        assert_eq!("S14".parse::<Card>(), Err(CardError::InvalidNumber(14)));
+        assert_eq!("X1".parse::<Card>(), Err(CardError::InvalidSuit('X')));
+        assert_eq!(
+            "S".parse::<Card>(),
+            Err(CardError::InvalidFormat("S".to_string()))
+        );
+        assert_eq!(
+            "".parse::<Card>(),
+            Err(CardError::InvalidFormat("".to_string()))
+        );
+    }
+
+    #[test]
+    fn wheel_same_suit_is_straight_flush() {
+        // A はホイールでは 1 として扱われ、ロイヤルにはならない
+        assert_eq!(Hands::wheel(Suit::Spade).rank(), Rank::StraightFlush);
+    }
+
+    #[test]
+    fn wheel_mixed_suits_is_straight() {
+        let wheel = hand![Spade 1, Heart 2, Clover 3, Diamond 4, Spade 5];
+        assert_eq!(wheel.rank(), Rank::Straight);
+        // 並び順に依存しない
+        let shuffled = hand![Diamond 4, Spade 1, Spade 5, Clover 3, Heart 2];
+        assert_eq!(shuffled.rank(), Rank::Straight);
+    }
+
+    #[test]
+    fn gutshot_is_not_wheel() {
+        // A,2,3,4,6 は 5 が欠けているためストレートではない
+        let gutshot = hand![Spade 1, Heart 2, Clover 3, Diamond 4, Spade 6];
+        assert_eq!(gutshot.rank(), Rank::HighCard(1));
+        // 同じスートでもフラッシュ止まり
+        let gutshot_flush = hand![Spade 1, Spade 2, Spade 3, Spade 4, Spade 6];
+        assert_eq!(gutshot_flush.rank(), Rank::Flush);
+        // A を挟んで折り返す Q,K,A,2,3 もストレートではない
+        let wrap_around = hand![Spade 12, Heart 13, Clover 1, Diamond 2, Spade 3];
+        assert_eq!(wrap_around.rank(), Rank::HighCard(1));
+    }
+
+    fn table() -> Table {
+        Table::new(vec![
+            Player::new(hand![Heart 9, Spade 9, Clover 2, Diamond 5, Heart 12], 100),
+            Player::new(Hands::royal(Suit::Spade), 100),
+            Player::new(hand![Heart 2, Spade 5, Clover 7, Diamond 9, Heart 1], 3),
+        ])
+    }
+
+    #[test]
+    fn table_ante() {
+        let mut table = table();
+        table.ante(10);
+
+        let chips: Vec<_> = table.players().iter().map(Player::chips).collect();
+        // 足りないプレイヤーは持っている全額を出す
+        assert_eq!(chips, [90, 90, 0]);
+        assert_eq!(table.pot(), 23);
+
+        table.ante(10);
+        assert_eq!(table.pot(), 43);
+    }
+
+    #[test]
+    fn table_settle() {
+        let mut table = table();
+        table.ante(10);
+
+        assert_eq!(table.settle(), 1);
+        assert_eq!(table.players()[1].chips(), 90 + 23);
+        assert_eq!(table.pot(), 0);
+        // チップの総数は変わらない
+        let total: u32 = table.players().iter().map(Player::chips).sum();
+        assert_eq!(total, 203);
+    }
+
+    #[test]
+    fn table_deal() {
+        let mut deck = Deck::new();
+        let table = Table::deal(&mut deck, 4, 50);
+
+        assert_eq!(table.players().len(), 4);
+        assert_eq!(deck.len(), 52 - 20);
+        assert!(table.players().iter().all(|p| p.chips() == 50));
+    }
+
+    #[test]
+    fn rank_players_distinct_categories() {
+        let hands = [
+            hand![Heart 9, Spade 9, Clover 2, Diamond 5, Heart 12],
+            Hands::royal(Suit::Spade),
+            hand![Heart 2, Spade 5, Clover 7, Diamond 9, Heart 1],
+            hand![Heart 9, Spade 9, Clover 9, Diamond 5, Heart 5],
+        ];
+        assert_eq!(rank_players(&hands), [[1], [3], [0], [2]]);
+    }
+
+    #[test]
+    fn rank_players_groups_ties() {
+        let hands = [
+            hand![Heart 2, Spade 5, Clover 7, Diamond 9, Heart 1],
+            hand![Heart 9, Spade 9, Clover 2, Diamond 5, Heart 12],
+            hand![Diamond 1, Clover 9, Heart 7, Spade 5, Diamond 2],
+            hand![Clover 9, Diamond 9, Spade 2, Heart 5, Spade 12],
+        ];
+        assert_eq!(rank_players(&hands), vec![vec![1, 3], vec![0, 2]]);
+        assert!(rank_players(&[]).is_empty());
+    }
+
+    /// 52 枚から 5 枚を選ぶ全ての組み合わせ (2,598,960 通り) を評価し、役ごとの数を
+    /// [`Rank::strength`] の順に数える。
+    fn enumerate_all_hands() -> [usize; 10] {
+        let cards = Card::all();
+        let n = cards.len();
+        let mut counts = [0; 10];
+
+        for a in 0..n {
+            for b in a + 1..n {
+                for c in b + 1..n {
+                    for d in c + 1..n {
+                        for e in d + 1..n {
+                            let hands = Hands([cards[a], cards[b], cards[c], cards[d], cards[e]]);
+                            counts[hands.rank().strength() as usize] += 1;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    #[ignore = "全ての手札を評価するため時間がかかる。cargo test -- --ignored で実行する"]
+    fn rank_frequencies_of_all_hands() {
+        // ストレートフラッシュはロイヤルを除いた 36 通り (ロイヤルを含めると 40 通り)
+        let expected = [
+            1_302_540, // HighCard
+            1_098_240, // OnePair
+            123_552,   // TwoPair
+            54_912,    // ThreeCard
+            10_200,    // Straight
+            5_108,     // Flush
+            3_744,     // FullHouse
+            624,       // FourCard
+            36,        // StraightFlush
+            4,         // RoyalStraightFlush
+        ];
+
+        let counts = enumerate_all_hands();
+        assert_eq!(counts, expected);
+        assert_eq!(counts.iter().sum::<usize>(), 2_598_960);
+    }
+
+    #[test]
+    fn all_cards_are_distinct() {
+        let cards = Card::all();
+        assert_eq!(cards.len(), 52);
+        for (i, card) in cards.iter().enumerate() {
+            assert!(!cards[i + 1..].contains(card), "{} appears twice", card);
+        }
+    }
+
+    #[test]
+    fn compact_display() {
+        let hands = hand![Spade 1, Heart 10, Clover 3, Diamond 12, Spade 7];
+        assert_eq!(format!("{:#}", hands), "[♠️A ❤️10 ♣️3 ♦️Q ♠️7]");
+        // 通常の表示は1行に1枚
+        assert_eq!(format!("{}", hands).lines().count(), 5);
+
+        let mut deck = Deck::new();
+        deck.stack_top(&[card(Suit::Heart, 1), card(Suit::Spade, 13)])
+            .unwrap();
+        assert!(deck.to_string().starts_with("[❤️A ♠️K "));
+    }
+
+    #[test]
+    fn exchange_returns_discarded_cards() {
+        let original = hand![Spade 1, Heart 10, Clover 3, Diamond 12, Spade 7];
+        let mut hands = original.clone();
+        let mut deck = Deck::new();
+        let replacements = [card(Suit::Heart, 2), card(Suit::Clover, 9)];
+        deck.stack_top(&replacements).unwrap();
+
+        let discarded = hands.exchange(&mut deck, &[0, 2]);
+
+        assert_eq!(discarded, [original[0], original[2]]);
+        assert_eq!(hands[0], replacements[0]);
+        assert_eq!(hands[2], replacements[1]);
+        for i in [1, 3, 4] {
+            assert_eq!(hands[i], original[i]);
+        }
+
+        // 同じ位置を重ねて指定しても、捨てるのは元のカード1枚だけ
+        let before = hands.clone();
+        assert_eq!(hands.exchange(&mut deck, &[4, 4]), [before[4]]);
+    }
+
+    #[test]
+    fn highest_and_lowest_card() {
+        let hands = hand![Heart 10, Spade 1, Clover 2, Diamond 13, Heart 2];
+        assert_eq!(hands.highest_card(), card(Suit::Spade, 1));
+        assert_eq!(hands.lowest_card(), card(Suit::Clover, 2));
+
+        let hands = hand![Heart 5, Spade 9, Clover 9, Diamond 3, Heart 4];
+        assert_eq!(hands.highest_card(), card(Suit::Spade, 9));
+        assert_eq!(hands.lowest_card(), card(Suit::Diamond, 3));
+    }
+
+    #[test]
+    fn cards_by_rank_desc() {
+        let hands = hand![Heart 10, Spade 1, Clover 2, Diamond 13, Clover 10];
+        assert_eq!(
+            hands.cards_by_rank_desc(),
+            [
+                card(Suit::Spade, 1),
+                card(Suit::Diamond, 13),
+                card(Suit::Heart, 10),
+                card(Suit::Clover, 10),
+                card(Suit::Clover, 2),
+            ]
+        );
+        assert_eq!(
+            card(Suit::Clover, 1).cmp_rank(&card(Suit::Spade, 13)),
+            Ordering::Greater
+        );
+        assert_eq!(
+            card(Suit::Clover, 5).cmp_rank(&card(Suit::Spade, 5)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn outcome_against_dealer() {
+        let kings_ace = hand![Spade 13, Heart 13, Clover 1, Diamond 7, Spade 3];
+        let kings_queen = hand![Clover 13, Diamond 13, Heart 12, Clover 7, Heart 3];
+        assert_eq!(Outcome::against(&kings_ace, &kings_queen), Outcome::Win);
+        assert_eq!(Outcome::against(&kings_queen, &kings_ace), Outcome::Lose);
+
+        let same_numbers = hand![Clover 13, Diamond 13, Heart 1, Clover 7, Heart 3];
+        assert_eq!(Outcome::against(&kings_ace, &same_numbers), Outcome::Tie);
+    }
+
+    #[test]
+    fn play_against_dealer() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let player = hand![Spade 13, Heart 13, Clover 1, Diamond 7, Spade 3];
+        let dealer = hand![Clover 13, Diamond 13, Heart 12, Clover 7, Heart 3];
+        let mut deck = Deck::with_rng(&mut StdRng::seed_from_u64(1));
+        deck.stack_top(&[player.to_vec(), dealer.to_vec()].concat())
+            .unwrap();
+
+        // 交換せずにスタンドする
+        let outcome = play(&mut deck, &mut "\n".as_bytes(), true);
+        assert_eq!(outcome, Some(Outcome::Win));
+        assert_eq!(deck.len(), 42);
+
+        // 3枚目のAを捨て、次に引く2と交換すると、キッカーで負ける
+        let mut deck = Deck::with_rng(&mut StdRng::seed_from_u64(1));
+        deck.stack_top(&[player.to_vec(), dealer.to_vec(), vec![card(Suit::Heart, 2)]].concat())
+            .unwrap();
+        let outcome = play(&mut deck, &mut "3\n\n".as_bytes(), true);
+        assert_eq!(outcome, Some(Outcome::Lose));
+
+        let mut deck = Deck::with_rng(&mut StdRng::seed_from_u64(1));
+        assert_eq!(play(&mut deck, &mut "\n".as_bytes(), false), None);
+    }
+
+    #[test]
+    fn write_to_reused_buffer() {
+        let mut deck = Deck::new();
+        let mut buf = String::new();
+        for _ in 0..10 {
+            let hands = Hands::new_from_deck(&mut deck);
+
+            buf.clear();
+            hands.write_to(&mut buf).unwrap();
+            assert_eq!(buf, hands.to_string());
+
+            buf.clear();
+            hands.write_compact_to(&mut buf).unwrap();
+            assert_eq!(buf, format!("{hands:#}"));
+
+            for card in hands.iter() {
+                buf.clear();
+                card.write_to(&mut buf).unwrap();
+                assert_eq!(buf, card.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn japanese_rank_names() {
+        let full_house = hand![Heart 3, Spade 3, Clover 3, Diamond 8, Heart 8];
+        assert_eq!(full_house.rank().describe(Locale::Ja), "フルハウス");
+        assert_eq!(Rank::OnePair.describe(Locale::Ja), "ワンペア");
+        assert_eq!(
+            Rank::RoyalStraightFlush.describe(Locale::Ja),
+            "ロイヤルストレートフラッシュ"
+        );
+        assert_eq!(Rank::HighCard(1).describe(Locale::Ja), "ハイカード (A)");
+        assert_eq!(Rank::HighCard(10).describe(Locale::Ja), "ハイカード (10)");
+        assert_eq!(
+            Rank::FiveOfAKind(12).describe(Locale::Ja),
+            "ファイブカード (Q)"
+        );
+    }
+
+    #[test]
+    fn english_rank_names_are_unchanged() {
+        for rank in [
+            Rank::HighCard(13),
+            Rank::TwoPair,
+            Rank::FullHouse,
+            Rank::FiveOfAKind(9),
+        ] {
+            assert_eq!(rank.describe(Locale::En), format!("{rank:?}"));
+        }
+        assert_eq!(Rank::FullHouse.describe(Locale::En), "FullHouse");
+    }
+
+    #[test]
+    fn card_names() {
+        assert_eq!(card(Suit::Heart, 1).describe(Locale::Ja), "ハートのA");
+        assert_eq!(
+            card(Suit::Clover, 10).describe(Locale::Ja),
+            "クローバーの10"
+        );
+        assert_eq!(card(Suit::Spade, 13).describe(Locale::En), "Spade K");
+        assert_eq!(Suit::Diamond.name(Locale::Ja), "ダイヤ");
+        // 絵文字の表示は言語によらない
+        assert_eq!(card(Suit::Heart, 1).to_string(), "❤️A");
+    }
+
+    #[test]
+    fn five_of_a_kind_with_joker() {
+        let nines = [
+            card(Suit::Heart, 9),
+            card(Suit::Spade, 9),
+            card(Suit::Clover, 9),
+            card(Suit::Diamond, 9),
+        ];
+        let (rank, tiebreak) = evaluate_with_jokers(&nines, 1).unwrap();
+        assert_eq!(rank, Rank::FiveOfAKind(9));
+
+        let royal = Hands::royal(Suit::Spade).ranked();
+        assert!(rank.strength() > royal.rank().strength());
+
+        // 5カード同士は数字で比べる。A は最も強い
+        let (aces, aces_tiebreak) = evaluate_with_jokers(&[card(Suit::Heart, 1)], 4).unwrap();
+        assert_eq!(aces, Rank::FiveOfAKind(1));
+        assert!(aces_tiebreak > tiebreak);
+    }
+
+    #[test]
+    fn joker_completes_the_best_hand() {
+        // 4枚のストレートフラッシュの一部 + ジョーカーは、ロイヤルを作る
+        let naturals = [
+            card(Suit::Heart, 10),
+            card(Suit::Heart, 11),
+            card(Suit::Heart, 12),
+            card(Suit::Heart, 13),
+        ];
+        assert_eq!(
+            evaluate_with_jokers(&naturals, 1).unwrap().0,
+            Rank::RoyalStraightFlush
+        );
+
+        // ペア + ジョーカーはスリーカード
+        let naturals = [
+            card(Suit::Heart, 5),
+            card(Suit::Spade, 5),
+            card(Suit::Clover, 2),
+            card(Suit::Diamond, 9),
+        ];
+        assert_eq!(
+            evaluate_with_jokers(&naturals, 1).unwrap().0,
+            Rank::ThreeCard
+        );
+
+        // ジョーカーがなければ通常の評価と同じ
+        let hands = hand![Heart 2, Spade 5, Diamond 7, Clover 9, Heart 12];
+        assert_eq!(
+            evaluate_with_jokers(&hands[..], 0),
+            Ok((hands.rank(), hands.tiebreak()))
+        );
+    }
+
+    #[test]
+    fn joker_tiebreaks() {
+        // 5,6 + ジョーカー3枚はストレートにもなれるが、フォーカードの方が強い
+        let naturals = [card(Suit::Heart, 5), card(Suit::Spade, 6)];
+        assert_eq!(
+            evaluate_with_jokers(&naturals, 3),
+            Ok((Rank::FourCard, vec![6, 5]))
+        );
+
+        // フラッシュのジョーカーは、手札にない最も強い数字になる
+        let naturals = [
+            card(Suit::Heart, 1),
+            card(Suit::Heart, 9),
+            card(Suit::Heart, 7),
+            card(Suit::Heart, 2),
+        ];
+        assert_eq!(
+            evaluate_with_jokers(&naturals, 1),
+            Ok((Rank::Flush, vec![14, 13, 9, 7, 2]))
+        );
+    }
+
+    #[test]
+    fn jokers_match_exhaustive_search() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        // ジョーカーを全てのカードに置き換えて、最も強い組み合わせを探す
+        fn best(cards: &mut Vec<Card>, jokers: usize) -> (u8, Vec<u8>) {
+            if jokers == 0 {
+                let stats = HandStats::from_cards(cards);
+                return (stats.rank().strength(), stats.tiebreak());
+            }
+            Card::all()
+                .into_iter()
+                .map(|card| {
+                    cards.push(card);
+                    let result = best(cards, jokers - 1);
+                    cards.pop();
+                    result
+                })
+                .max()
+                .unwrap()
+        }
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for jokers in 1..=2 {
+            for _ in 0..200 {
+                let mut cards = Card::all();
+                cards.shuffle(&mut rng);
+                let naturals = &cards[..5 - jokers];
+                let (rank, tiebreak) = evaluate_with_jokers(naturals, jokers).unwrap();
+                assert_eq!(
+                    (rank.strength(), tiebreak),
+                    best(&mut naturals.to_vec(), jokers),
+                    "{naturals:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_with_jokers_wrong_count() {
+        let cards = Hands::royal(Suit::Heart).to_vec();
+
+        assert_eq!(
+            evaluate_with_jokers(&cards[..4], 0),
+            Err(HandsError::WrongCount(4))
+        );
+        assert_eq!(
+            evaluate_with_jokers(&cards, 1),
+            Err(HandsError::WrongCount(6))
+        );
+        assert_eq!(
+            evaluate_with_jokers(&cards[..4], 2),
+            Err(HandsError::WrongCount(6))
+        );
+        assert_eq!(evaluate_with_jokers(&[], 4), Err(HandsError::WrongCount(4)));
+    }
+
+    /// ハイカード同士の比較。5枚全ての数字を上から順に比べる。
+    mod high_card {
+        use super::*;
+
+        use Ordering::*;
+
+        #[test]
+        fn compare_high_card_pairs() {
+            #[rustfmt::skip]
+            let cases = [
+                // 上位4枚が同じで、5枚目だけが異なる
+                (hand![Heart 1, Spade 13, Clover 9, Diamond 7, Heart 3],
+                 hand![Spade 1, Heart 13, Diamond 9, Clover 7, Spade 2], Greater),
+                (hand![Heart 12, Spade 10, Clover 8, Diamond 6, Heart 2],
+                 hand![Spade 12, Heart 10, Diamond 8, Clover 6, Spade 3], Less),
+                // 2枚目で決まる
+                (hand![Heart 1, Spade 12, Clover 9, Diamond 7, Heart 3],
+                 hand![Spade 1, Heart 11, Diamond 10, Clover 8, Spade 6], Greater),
+                // A ハイは K ハイより強い
+                (hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 6],
+                 hand![Spade 13, Heart 12, Diamond 11, Clover 9, Spade 8], Greater),
+                (hand![Heart 13, Spade 12, Clover 11, Diamond 10, Heart 8],
+                 hand![Spade 1, Heart 5, Diamond 4, Clover 3, Spade 7], Less),
+                // スートは比較に使わない
+                (hand![Heart 1, Heart 13, Heart 9, Heart 7, Spade 3],
+                 hand![Spade 1, Clover 13, Clover 9, Diamond 7, Diamond 3], Equal),
+                // ホイール(A2345)はストレートなので、Aを含むハイカードより強い
+                (hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 5],
+                 hand![Spade 1, Heart 13, Diamond 12, Clover 11, Spade 9], Greater),
+                // A2346はホイールに届かないハイカード。Aを14として扱う
+                (hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 6],
+                 hand![Spade 1, Heart 2, Diamond 3, Clover 4, Spade 7], Less),
+                (hand![Heart 1, Spade 2, Clover 3, Diamond 4, Heart 6],
+                 hand![Spade 1, Heart 2, Diamond 3, Clover 5, Spade 6], Less),
+            ];
+
+            for (a, b, expected) in cases {
+                assert_eq!(a.compare(&b), expected, "{:?} vs {:?}", a, b);
+                assert_eq!(b.compare(&a), expected.reverse(), "{:?} vs {:?}", b, a);
+                assert_eq!(a.clone().ranked().compare(&b.clone().ranked()), expected);
+            }
+        }
+    }
 }